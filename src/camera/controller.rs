@@ -0,0 +1,219 @@
+use glam::Quat;
+use glam::Vec2;
+use glam::Vec3;
+
+use super::Camera;
+use super::input::{InputSource, Key, MouseButton};
+
+//Pitch limit of the controllers that keep the world up, just short of straight up or down
+const MAX_PITCH: f32 = std::f32::consts::PI * 0.49;
+
+//Moves a camera from the input of a frame
+pub trait CameraController {
+    fn update(&mut self, camera: &mut Camera, input: &dyn InputSource, dt: f32);
+}
+
+//Fly through, right drag looks around, WASD moves along the view, QE moves down and up, shift speeds up
+#[derive(Debug, Clone)]
+pub struct FirstPersonController {
+    //World units per second
+    pub move_speed: f32,
+    //Radians per pixel
+    pub look_speed: f32,
+    pub boost: f32
+}
+
+impl Default for FirstPersonController {
+    fn default() -> Self {
+        Self { move_speed: 1.0, look_speed: 0.01, boost: 3.0 }
+    }
+}
+
+impl CameraController for FirstPersonController {
+    fn update(&mut self, camera: &mut Camera, input: &dyn InputSource, dt: f32) {
+
+        //Rotation
+        if input.is_button_down(MouseButton::Right) {
+            let delta = input.mouse_delta() * self.look_speed;
+            camera.euler_rotation.x -= delta.x;
+            camera.euler_rotation.y = (camera.euler_rotation.y - delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let camera_front = camera.get_front();
+        let camera_right = camera.get_right();
+
+        //Movement
+        let movement_delta = [
+            (Key::W, camera_front), (Key::S, -camera_front),
+            (Key::D, camera_right), (Key::A, -camera_right),
+            (Key::E, Vec3::Y), (Key::Q, Vec3::NEG_Y)
+        ].iter().filter(|(key, _)| input.is_key_down(*key)).map(|(_, direction)| *direction).sum::<Vec3>();
+
+        let speed = if input.is_key_down(Key::LeftShift) { self.move_speed * self.boost } else { self.move_speed };
+
+        //Normalize and apply movement
+        if let Some(direction) = movement_delta.try_normalize() {
+            camera.position += direction * speed * dt;
+        }
+    }
+}
+
+//Shared by the controllers circling a target, scroll zooms and middle drag pans
+#[derive(Debug, Clone)]
+pub struct Orbit {
+    pub target: Vec3,
+    pub distance: f32,
+    pub min_distance: f32,
+    //Fraction of the distance per scroll step
+    pub zoom_speed: f32,
+    //Fraction of the distance per pixel
+    pub pan_speed: f32
+}
+
+impl Default for Orbit {
+    fn default() -> Self {
+        Self { target: Vec3::ZERO, distance: 2.0, min_distance: 0.01, zoom_speed: 0.1, pan_speed: 0.002 }
+    }
+}
+
+impl Orbit {
+    fn zoom_and_pan(&mut self, rotation: Quat, input: &dyn InputSource) {
+        self.distance = (self.distance * (-input.scroll_delta() * self.zoom_speed).exp()).max(self.min_distance);
+
+        if input.is_button_down(MouseButton::Middle) {
+            //Dragging moves the scene with the cursor
+            let delta = input.mouse_delta() * self.pan_speed * self.distance;
+            self.target += rotation * Vec3::new(-delta.x, delta.y, 0.0);
+        }
+    }
+
+    fn place(&self, camera: &mut Camera, rotation: Quat) {
+        camera.position = self.target + rotation * Vec3::Z * self.distance;
+
+        let (yaw, pitch, roll) = rotation.to_euler(glam::EulerRot::YXZ);
+        camera.euler_rotation = Vec3::new(yaw, pitch, roll);
+    }
+}
+
+//Arcball, right drag rotates freely about the view axes, so the camera can roll and go over the top
+#[derive(Debug, Clone)]
+pub struct OrbitController {
+    pub orbit: Orbit,
+    pub rotation: Quat,
+    //Radians per pixel
+    pub rotate_speed: f32
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self { orbit: Orbit::default(), rotation: Quat::IDENTITY, rotate_speed: 0.01 }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, camera: &mut Camera, input: &dyn InputSource, _dt: f32) {
+
+        if input.is_button_down(MouseButton::Right) {
+            let delta: Vec2 = input.mouse_delta() * self.rotate_speed;
+            self.rotation = (self.rotation * Quat::from_rotation_y(-delta.x) * Quat::from_rotation_x(-delta.y)).normalize();
+        }
+
+        self.orbit.zoom_and_pan(self.rotation, input);
+        self.orbit.place(camera, self.rotation);
+    }
+}
+
+//Right drag spins around the world up and tilts up to MAX_PITCH, the horizon always stays level
+#[derive(Debug, Clone)]
+pub struct TurntableController {
+    pub orbit: Orbit,
+    pub yaw: f32,
+    pub pitch: f32,
+    //Radians per pixel
+    pub rotate_speed: f32
+}
+
+impl Default for TurntableController {
+    fn default() -> Self {
+        Self { orbit: Orbit::default(), yaw: 0.0, pitch: 0.0, rotate_speed: 0.01 }
+    }
+}
+
+impl CameraController for TurntableController {
+    fn update(&mut self, camera: &mut Camera, input: &dyn InputSource, _dt: f32) {
+
+        if input.is_button_down(MouseButton::Right) {
+            let delta = input.mouse_delta() * self.rotate_speed;
+            self.yaw -= delta.x;
+            self.pitch = (self.pitch - delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let rotation = Quat::from_euler(glam::EulerRot::YXZ, self.yaw, self.pitch, 0.0);
+        self.orbit.zoom_and_pan(rotation, input);
+        self.orbit.place(camera, rotation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use crate::camera::Camera;
+    use crate::camera::input::{InputEvent, InputState, Key, MouseButton};
+    use super::{CameraController, FirstPersonController, OrbitController, TurntableController, MAX_PITCH};
+
+    fn drag(button: MouseButton, delta: Vec2) -> InputState {
+        let mut input = InputState::default();
+        input.apply(InputEvent::ButtonDown(button));
+        input.apply(InputEvent::MouseMove(delta));
+        input
+    }
+
+    #[test]
+    fn first_person_moves_along_the_view() {
+        let mut camera = Camera::default();
+        let mut controller = FirstPersonController::default();
+
+        let mut input = InputState::default();
+        input.apply(InputEvent::KeyDown(Key::W));
+        controller.update(&mut camera, &input, 0.5);
+        assert!(camera.position.abs_diff_eq(Vec3::new(0.0, 0.0, -0.5), 1e-6));
+
+        //Quarter turn to the right, forward is now +x
+        let input = drag(MouseButton::Right, Vec2::new(std::f32::consts::FRAC_PI_2 / controller.look_speed, 0.0));
+        controller.update(&mut camera, &input, 0.0);
+        assert!(camera.get_front().abs_diff_eq(Vec3::X, 1e-5));
+    }
+
+    #[test]
+    fn orbit_rotates_zooms_and_pans_around_the_target() {
+        let mut camera = Camera::default();
+        let mut controller = OrbitController::default();
+
+        controller.update(&mut camera, &drag(MouseButton::Right, Vec2::new(40.0, 170.0)), 0.0);
+        assert!((camera.position.distance(controller.orbit.target) - 2.0).abs() < 1e-5);
+        assert!(camera.get_front().abs_diff_eq((controller.orbit.target - camera.position).normalize(), 1e-5));
+
+        let mut input = InputState::default();
+        input.apply(InputEvent::Scroll(1.0));
+        controller.update(&mut camera, &input, 0.0);
+        assert!(controller.orbit.distance < 2.0);
+
+        let before = controller.orbit.target;
+        controller.update(&mut camera, &drag(MouseButton::Middle, Vec2::new(100.0, 0.0)), 0.0);
+        let moved = controller.orbit.target - before;
+        assert!(moved.dot(camera.get_right()) < 0.0);
+        assert!(moved.dot(camera.get_front()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn turntable_keeps_the_horizon_level() {
+        let mut camera = Camera::default();
+        let mut controller = TurntableController::default();
+
+        controller.update(&mut camera, &drag(MouseButton::Right, Vec2::new(75.0, -1000.0)), 0.0);
+
+        assert_eq!(controller.pitch, MAX_PITCH);
+        assert!(camera.get_right().y.abs() < 1e-5);
+        assert!((camera.position.distance(controller.orbit.target) - 2.0).abs() < 1e-5);
+    }
+}
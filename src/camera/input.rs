@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+use glam::Vec2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    Q,
+    E,
+    LeftShift
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle
+}
+
+//What the controllers read each frame, deltas are since the previous frame
+pub trait InputSource {
+    fn is_key_down(&self, key: Key) -> bool;
+    fn is_button_down(&self, button: MouseButton) -> bool;
+    //In pixels, y points down the screen
+    fn mouse_delta(&self) -> Vec2;
+    //Positive scrolls away from the user
+    fn scroll_delta(&self) -> f32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    KeyDown(Key),
+    KeyUp(Key),
+    ButtonDown(MouseButton),
+    ButtonUp(MouseButton),
+    MouseMove(Vec2),
+    Scroll(f32)
+}
+
+//Input built from events, held keys and buttons persist while deltas accumulate until end_frame
+#[derive(Debug, Default, Clone)]
+pub struct InputState {
+    keys: HashSet<Key>,
+    buttons: HashSet<MouseButton>,
+    mouse_delta: Vec2,
+    scroll_delta: f32,
+    //Last cursor position seen by poll_window
+    mouse_position: Option<Vec2>
+}
+
+impl InputState {
+
+    pub fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::KeyDown(key) => { self.keys.insert(key); }
+            InputEvent::KeyUp(key) => { self.keys.remove(&key); }
+            InputEvent::ButtonDown(button) => { self.buttons.insert(button); }
+            InputEvent::ButtonUp(button) => { self.buttons.remove(&button); }
+            InputEvent::MouseMove(delta) => self.mouse_delta += delta,
+            InputEvent::Scroll(delta) => self.scroll_delta += delta
+        }
+    }
+
+    //Clears the deltas once the frame has consumed them
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+
+    //Replaces the held keys and buttons with the window state and adds its cursor and wheel movement
+    pub fn poll_window(&mut self, window: &minifb::Window) {
+        self.keys = window.get_keys().iter().filter_map(|key| match key {
+            minifb::Key::W => Some(Key::W),
+            minifb::Key::A => Some(Key::A),
+            minifb::Key::S => Some(Key::S),
+            minifb::Key::D => Some(Key::D),
+            minifb::Key::Q => Some(Key::Q),
+            minifb::Key::E => Some(Key::E),
+            minifb::Key::LeftShift => Some(Key::LeftShift),
+            _ => None
+        }).collect();
+
+        self.buttons = [
+            (minifb::MouseButton::Left, MouseButton::Left),
+            (minifb::MouseButton::Right, MouseButton::Right),
+            (minifb::MouseButton::Middle, MouseButton::Middle)
+        ].iter().filter(|(source, _)| window.get_mouse_down(*source)).map(|(_, button)| *button).collect();
+
+        if let Some((x, y)) = window.get_mouse_pos(minifb::MouseMode::Discard) {
+            let position = Vec2::new(x, y);
+            if let Some(previous) = self.mouse_position {
+                self.mouse_delta += position - previous;
+            }
+            self.mouse_position = Some(position);
+        }
+
+        if let Some((_, y)) = window.get_scroll_wheel() {
+            self.scroll_delta += y;
+        }
+    }
+}
+
+impl InputSource for InputState {
+    fn is_key_down(&self, key: Key) -> bool { self.keys.contains(&key) }
+    fn is_button_down(&self, button: MouseButton) -> bool { self.buttons.contains(&button) }
+    fn mouse_delta(&self) -> Vec2 { self.mouse_delta }
+    fn scroll_delta(&self) -> f32 { self.scroll_delta }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use super::{InputEvent, InputSource, InputState, Key, MouseButton};
+
+    #[test]
+    fn events_hold_and_accumulate() {
+        let mut input = InputState::default();
+        input.apply(InputEvent::KeyDown(Key::W));
+        input.apply(InputEvent::ButtonDown(MouseButton::Right));
+        input.apply(InputEvent::MouseMove(Vec2::new(2.0, 1.0)));
+        input.apply(InputEvent::MouseMove(Vec2::new(1.0, 1.0)));
+        input.apply(InputEvent::Scroll(1.0));
+
+        assert!(input.is_key_down(Key::W));
+        assert!(input.is_button_down(MouseButton::Right));
+        assert_eq!(input.mouse_delta(), Vec2::new(3.0, 2.0));
+
+        input.end_frame();
+        input.apply(InputEvent::KeyUp(Key::W));
+
+        assert!(!input.is_key_down(Key::W));
+        assert!(input.is_button_down(MouseButton::Right));
+        assert_eq!(input.mouse_delta(), Vec2::ZERO);
+        assert_eq!(input.scroll_delta(), 0.0);
+    }
+}
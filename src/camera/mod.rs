@@ -1,13 +1,40 @@
+use crate::math;
 use crate::math::plane::Plane;
 
+pub mod controller;
+pub mod input;
+pub mod path;
+
+//How the view volume is mapped to clip space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    //Vertical field of view in radians, the horizontal one follows the aspect ratio
+    Perspective { fov: f32 },
+    //Height of the view volume in world units, the width follows the aspect ratio
+    Orthographic { size: f32 },
+    //Asymmetric frustum given by its extents on the near plane, the aspect ratio is ignored
+    OffAxis { left: f32, right: f32, bottom: f32, top: f32 }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective { fov: std::f32::consts::FRAC_PI_4 }
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct Camera {
     pub position: glam::Vec3,
     pub euler_rotation: glam::Vec3,
-    pub fov: f32,
+    pub projection: Projection,
     pub aspect_ratio: f32,
     pub near: f32,
-    pub far: f32
+    pub far: f32,
+    //Maps near to 1 and far to 0, pair it with a Greater depth test and a far_depth clear
+    //Clippers follow both options through ClipState::set_camera
+    pub reversed_z: bool,
+    //Ignores far and puts the far plane at infinity, orthographic projections always use far
+    pub infinite_far: bool
 }
 
 impl Camera {
@@ -28,78 +55,152 @@ impl Camera {
         self.get_rotation().mul_vec3(glam::Vec3::Y)
     }
 
-    pub fn generate_frustum_perspective(&self) -> Vec<Plane> {
-
-        let half_v = self.far * (self.fov * 0.5).tan();
-        let half_h = half_v * self.aspect_ratio;
-
-        let camera_front = self.get_front();
-        let camera_right = self.get_right();
-        let camera_up = self.get_up();
+    //World space planes of the view volume facing inwards, in the order near, far, right, left, top, bottom
+    //Far is left out for infinite projections
+    pub fn generate_frustum(&self) -> Vec<Plane> {
 
-        let near_vector = camera_front * self.near;
-        let near = Plane::from_normal_point(camera_front, self.position + near_vector);
+        let (view, projection) = self.generate_view_projection();
+        let view_projection = projection * view;
 
-        let far_vector = camera_front * self.far;
-        let far = Plane::from_normal_point(-camera_front, self.position + far_vector);
+        //Clip space planes pulled back through the transform, near and far swap with reversed-Z
+        let [z_min, left, right, bottom, top, z_max] = math::CLIP_PLANES.map(|plane| view_projection.transpose() * plane);
+        let (near, far) = if self.reversed_z { (z_max, z_min) } else { (z_min, z_max) };
 
-        let right_normal = (far_vector - camera_right * half_h).cross(camera_up).normalize(); 
-        let right = Plane::from_normal_point(right_normal, self.position);
-
-        let left_normal = -(far_vector + camera_right * half_h).cross(camera_up).normalize();
-        let left = Plane::from_normal_point(left_normal, self.position);
-
-        let top_normal = (far_vector + camera_up * half_v).cross(camera_right).normalize();
-        let top = Plane::from_normal_point(top_normal, self.position);
-
-        let bottom_normal = -(far_vector - camera_up * half_v).cross(camera_right).normalize();
-        let bottom = Plane::from_normal_point(bottom_normal, self.position);
-
-        vec![near, far, right, left, top, bottom]
+        [near, far, right, left, top, bottom].into_iter()
+            .filter_map(Plane::from_vec4)
+            .collect()
     }
 
     pub fn generate_view_projection(&self) -> (glam::Mat4, glam::Mat4) {
         (
             glam::Mat4::from_rotation_translation(self.get_rotation(), self.position).inverse(),
-            glam::Mat4::perspective_rh(self.fov, self.aspect_ratio, self.near, self.far)
+            self.generate_projection()
         )
     }
-}
 
-pub fn first_person_controls(camera : &mut Camera, input: &minifb::Window, mouse_delta: glam::Vec2, dt: f32) {
+    pub fn generate_projection(&self) -> glam::Mat4 {
+        match self.projection {
+            Projection::Perspective { fov } => match (self.reversed_z, self.infinite_far) {
+                (false, false) => glam::Mat4::perspective_rh(fov, self.aspect_ratio, self.near, self.far),
+                (false, true) => glam::Mat4::perspective_infinite_rh(fov, self.aspect_ratio, self.near),
+                //Swapping the planes flips the depth range
+                (true, false) => glam::Mat4::perspective_rh(fov, self.aspect_ratio, self.far, self.near),
+                (true, true) => glam::Mat4::perspective_infinite_reverse_rh(fov, self.aspect_ratio, self.near)
+            },
+            Projection::Orthographic { size } => {
+                let half_height = size * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                let (near, far) = if self.reversed_z { (self.far, self.near) } else { (self.near, self.far) };
+
+                glam::Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, near, far)
+            },
+            Projection::OffAxis { left, right, bottom, top } => self.off_axis_projection(left, right, bottom, top)
+        }
+    }
+
+    //Right handed frustum looking down -z with depth in [0, 1], like perspective_rh
+    fn off_axis_projection(&self, left: f32, right: f32, bottom: f32, top: f32) -> glam::Mat4 {
+        let near = self.near;
+
+        //Depth is z_scale * z + z_offset over w = -z
+        let (z_scale, z_offset) = match (self.reversed_z, self.infinite_far) {
+            (false, false) => (self.far / (near - self.far), near * self.far / (near - self.far)),
+            (false, true) => (-1.0, -near),
+            (true, false) => (near / (self.far - near), near * self.far / (self.far - near)),
+            (true, true) => (0.0, near)
+        };
+
+        glam::Mat4::from_cols(
+            glam::Vec4::new(2.0 * near / (right - left), 0.0, 0.0, 0.0),
+            glam::Vec4::new(0.0, 2.0 * near / (top - bottom), 0.0, 0.0),
+            glam::Vec4::new((right + left) / (right - left), (top + bottom) / (top - bottom), z_scale, -1.0),
+            glam::Vec4::new(0.0, 0.0, z_offset, 0.0)
+        )
+    }
 
-    //Rotation
-    if input.get_mouse_down(minifb::MouseButton::Right) {
+    //Orthographic projections always use far
+    pub fn has_infinite_far(&self) -> bool {
+        self.infinite_far && !matches!(self.projection, Projection::Orthographic { .. })
+    }
 
-        camera.euler_rotation.x -= mouse_delta.x * 0.01;
-        camera.euler_rotation.y -= mouse_delta.y * 0.01;
+    //Depth of the far plane, what the depth buffer is cleared to
+    pub fn far_depth(&self) -> f64 {
+        if self.reversed_z { 0.0 } else { 1.0 }
+    }
+}
 
-        camera.euler_rotation.y = camera.euler_rotation.y.clamp(
-            -std::f32::consts::PI * 0.49, std::f32::consts::PI * 0.49
-        );
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4};
+    use super::{Camera, Projection};
+
+    fn depth_at(camera: &Camera, distance: f32) -> f32 {
+        let clip = camera.generate_projection() * Vec4::new(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
     }
 
-    let camera_front = camera.get_front();
-    let camera_right = camera.get_right();
-    let camera_up = camera.get_up();
-    
-    //Movement
-    let mut movement_delta = glam::Vec3::default();
-
-    input.get_keys().iter().for_each(|key|
-        match key {
-            minifb::Key::S => movement_delta -= camera_front,
-            minifb::Key::W => movement_delta += camera_front,
-            minifb::Key::A => movement_delta -= camera_right,
-            minifb::Key::D => movement_delta += camera_right,
-            minifb::Key::E => movement_delta += glam::Vec3::Y,
-            minifb::Key::Q => movement_delta += glam::Vec3::NEG_Y,
-            _ => (),
+    #[test]
+    fn depth_ranges_follow_the_options() {
+        let mut camera = Camera {
+            projection: Projection::Perspective { fov: 1.0 }, aspect_ratio: 1.0, near: 0.1, far: 100.0, ..Default::default()
+        };
+
+        assert!(depth_at(&camera, 0.1).abs() < 1e-5);
+        assert!((depth_at(&camera, 100.0) - 1.0).abs() < 1e-5);
+
+        camera.reversed_z = true;
+        assert!((depth_at(&camera, 0.1) - 1.0).abs() < 1e-5);
+        assert!(depth_at(&camera, 100.0).abs() < 1e-5);
+        assert_eq!(camera.far_depth(), 0.0);
+
+        //Nothing is too far, depth only approaches 0
+        camera.infinite_far = true;
+        let distant = depth_at(&camera, 1.0e6);
+        assert!(distant > 0.0 && distant < 1e-6);
+        assert!(depth_at(&camera, 1.0) > depth_at(&camera, 2.0));
+    }
+
+    #[test]
+    fn off_axis_matches_symmetric_perspective() {
+        let perspective = Camera {
+            projection: Projection::Perspective { fov: 1.0 }, aspect_ratio: 1.5, near: 0.1, far: 100.0, ..Default::default()
+        };
+
+        let top = 0.1 * 0.5f32.tan();
+        let off_axis = Camera {
+            projection: Projection::OffAxis { left: -top * 1.5, right: top * 1.5, bottom: -top, top }, ..perspective.clone()
+        };
+
+        for reversed_z in [false, true] {
+            for infinite_far in [false, true] {
+                let a = Camera { reversed_z, infinite_far, ..perspective.clone() }.generate_projection();
+                let b = Camera { reversed_z, infinite_far, ..off_axis.clone() }.generate_projection();
+                assert!(a.abs_diff_eq(b, 1e-4), "reversed {reversed_z} infinite {infinite_far}");
+            }
         }
-    );
+    }
 
-    //Normalize and apply movement
-    if movement_delta.dot(movement_delta).abs() > std::f32::EPSILON {
-        camera.position += movement_delta.normalize() * dt;
+    #[test]
+    fn frustum_planes_follow_the_projection() {
+        let camera = Camera {
+            projection: Projection::Orthographic { size: 4.0 }, aspect_ratio: 2.0, near: 1.0, far: 10.0, ..Default::default()
+        };
+
+        let frustum = camera.generate_frustum();
+        assert_eq!(frustum.len(), 6);
+
+        let inside = |point: Vec3| frustum.iter().all(|plane| plane.signed_distance(point) >= -1e-4);
+        assert!(inside(Vec3::new(3.9, 1.9, -5.0)));
+        assert!(!inside(Vec3::new(4.1, 0.0, -5.0)));
+        assert!(!inside(Vec3::new(0.0, 2.1, -5.0)));
+        assert!(!inside(Vec3::new(0.0, 0.0, -0.5)));
+        assert!(!inside(Vec3::new(0.0, 0.0, -10.5)));
+
+        //Near first, the far plane is dropped at infinity
+        let perspective = Camera { projection: Projection::default(), infinite_far: true, reversed_z: true, ..camera };
+        let frustum = perspective.generate_frustum();
+        assert_eq!(frustum.len(), 5);
+        assert!(frustum[0].signed_distance(Vec3::new(0.0, 0.0, -1.5)) > 0.0);
+        assert!(frustum[0].signed_distance(Vec3::new(0.0, 0.0, -0.5)) < 0.0);
     }
-}
\ No newline at end of file
+}
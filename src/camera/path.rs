@@ -0,0 +1,210 @@
+use std::path::Path;
+
+use glam::Quat;
+use glam::Vec3;
+
+use super::Camera;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    //Seconds from the start of the path
+    pub time: f32,
+    pub position: Vec3,
+    pub rotation: Quat
+}
+
+//Camera poses over time, positions follow a Catmull-Rom spline through the keyframes and rotations are slerped
+//Recording every frame and playing back at a fixed step gives the same fly-through on every run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CameraPath {
+    //Sorted by time
+    pub keyframes: Vec<CameraKeyframe>
+}
+
+impl CameraPath {
+
+    //Appends the current pose, times have to be finite and keep increasing
+    pub fn record(&mut self, time: f32, camera: &Camera) {
+        if !time.is_finite() || self.keyframes.last().is_some_and(|last| time <= last.time) { return; }
+        self.keyframes.push(CameraKeyframe { time, position: camera.position, rotation: camera.get_rotation() });
+    }
+
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0
+        }
+    }
+
+    //Pose at a time, clamped to the ends of the path, None if it has no keyframes, NaN gives the first pose
+    pub fn sample(&self, time: f32) -> Option<(Vec3, Quat)> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+
+        if time.is_nan() || time <= first.time { return Some((first.position, first.rotation)); }
+        if time >= last.time { return Some((last.position, last.rotation)); }
+
+        //Segment from k1 to k2 holding the time
+        let segment = self.keyframes.partition_point(|keyframe| keyframe.time <= time) - 1;
+        let k1 = &self.keyframes[segment];
+        let k2 = &self.keyframes[segment + 1];
+
+        //Missing neighbours at the ends are mirrored, which keeps the end segments straight
+        let (t0, p0) = match segment.checked_sub(1).map(|index| &self.keyframes[index]) {
+            Some(k0) => (k0.time, k0.position),
+            None => (2.0 * k1.time - k2.time, 2.0 * k1.position - k2.position)
+        };
+        let (t3, p3) = match self.keyframes.get(segment + 2) {
+            Some(k3) => (k3.time, k3.position),
+            None => (2.0 * k2.time - k1.time, 2.0 * k2.position - k1.position)
+        };
+
+        let span = k2.time - k1.time;
+        let u = (time - k1.time) / span;
+
+        //Catmull-Rom tangents scaled for uneven keyframe spacing, evaluated as a cubic Hermite segment
+        let m1 = (k2.position - p0) / (k2.time - t0) * span;
+        let m2 = (p3 - k1.position) / (t3 - k1.time) * span;
+
+        let (u2, u3) = (u * u, u * u * u);
+        let position = k1.position * (2.0 * u3 - 3.0 * u2 + 1.0)
+            + m1 * (u3 - 2.0 * u2 + u)
+            + k2.position * (-2.0 * u3 + 3.0 * u2)
+            + m2 * (u3 - u2);
+
+        Some((position, k1.rotation.slerp(k2.rotation, u)))
+    }
+
+    //Moves the camera to the pose at a time, false if the path is empty
+    pub fn apply(&self, time: f32, camera: &mut Camera) -> bool {
+        let Some((position, rotation)) = self.sample(time) else { return false; };
+
+        let (yaw, pitch, roll) = rotation.to_euler(glam::EulerRot::YXZ);
+        camera.position = position;
+        camera.euler_rotation = Vec3::new(yaw, pitch, roll);
+        true
+    }
+
+    //One keyframe per line: time, position xyz and rotation quaternion xyzw
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# time px py pz qx qy qz qw\n");
+        for keyframe in &self.keyframes {
+            let (p, q) = (keyframe.position, keyframe.rotation);
+            text += &format!("{} {} {} {} {} {} {} {}\n", keyframe.time, p.x, p.y, p.z, q.x, q.y, q.z, q.w);
+        }
+        text
+    }
+
+    //Blank lines and lines starting with # are skipped, errors carry the line number
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut path = CameraPath::default();
+
+        for (number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            //NaN and infinities are rejected like any other invalid number
+            let values = line.split_whitespace()
+                .map(|value| value.parse::<f32>().ok().filter(|value| value.is_finite()).ok_or_else(|| format!("line {number}: invalid number '{value}'")))
+                .collect::<Result<Vec<f32>, String>>()?;
+
+            let [time, px, py, pz, qx, qy, qz, qw] = values[..] else {
+                return Err(format!("line {number}: expected 8 values, found {}", values.len()));
+            };
+
+            if path.keyframes.last().is_some_and(|last| time <= last.time) {
+                return Err(format!("line {number}: time {time} does not increase"));
+            }
+
+            let rotation = glam::Vec4::new(qx, qy, qz, qw).try_normalize()
+                .ok_or_else(|| format!("line {number}: rotation has zero length"))?;
+
+            path.keyframes.push(CameraKeyframe { time, position: Vec3::new(px, py, pz), rotation: Quat::from_vec4(rotation) });
+        }
+
+        Ok(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_text()).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        Self::from_text(&text).map_err(|error| format!("{}: {error}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+    use crate::camera::Camera;
+    use super::{CameraKeyframe, CameraPath};
+
+    fn keyframe(time: f32, position: Vec3, yaw: f32) -> CameraKeyframe {
+        CameraKeyframe { time, position, rotation: Quat::from_rotation_y(yaw) }
+    }
+
+    #[test]
+    fn spline_passes_through_keyframes() {
+        let path = CameraPath { keyframes: vec![
+            keyframe(0.0, Vec3::ZERO, 0.0),
+            keyframe(1.0, Vec3::X, 1.0),
+            keyframe(3.0, Vec3::new(1.0, 2.0, 0.0), 2.0)
+        ]};
+
+        for keyframe in &path.keyframes {
+            let (position, rotation) = path.sample(keyframe.time).unwrap();
+            assert!(position.abs_diff_eq(keyframe.position, 1e-6));
+            assert!(rotation.abs_diff_eq(keyframe.rotation, 1e-6));
+        }
+
+        //Evenly spaced points on a line stay on it, rotations are halfway
+        let straight = CameraPath { keyframes: vec![keyframe(0.0, Vec3::ZERO, 0.0), keyframe(1.0, Vec3::X, 1.0), keyframe(2.0, Vec3::X * 2.0, 2.0)] };
+        let (position, rotation) = straight.sample(0.5).unwrap();
+        assert!(position.abs_diff_eq(Vec3::X * 0.5, 1e-6));
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_y(0.5), 1e-6));
+
+        assert_eq!(straight.sample(-1.0).unwrap().0, Vec3::ZERO);
+        assert_eq!(straight.sample(5.0).unwrap().0, Vec3::X * 2.0);
+        assert!(CameraPath::default().sample(0.0).is_none());
+        assert_eq!(straight.sample(f32::NAN).unwrap().0, Vec3::ZERO);
+    }
+
+    #[test]
+    fn recordings_round_trip_through_text() {
+        let mut camera = Camera::default();
+        let mut path = CameraPath::default();
+
+        for frame in 0..4 {
+            camera.position = Vec3::new(frame as f32, 0.5, -0.25);
+            camera.euler_rotation = Vec3::new(frame as f32 * 0.1, 0.2, 0.0);
+            path.record(frame as f32 / 60.0, &camera);
+        }
+
+        let loaded = CameraPath::from_text(&path.to_text()).unwrap();
+        assert_eq!(loaded.keyframes.len(), 4);
+
+        let mut replayed = Camera::default();
+        assert!(loaded.apply(3.0 / 60.0, &mut replayed));
+        assert!(replayed.position.abs_diff_eq(camera.position, 1e-5));
+        assert!(replayed.get_rotation().abs_diff_eq(camera.get_rotation(), 1e-5));
+    }
+
+    #[test]
+    fn malformed_keyframes_are_rejected() {
+        let missing = CameraPath::from_text("# header\n0 0 0 0 0 0 0 1\n\n1 0 0 0 0 0 1\n");
+        assert_eq!(missing.unwrap_err(), "line 4: expected 8 values, found 7");
+
+        let invalid = CameraPath::from_text("0 0 0 0 0 0 0 1\n1 0 x 0 0 0 0 1\n");
+        assert_eq!(invalid.unwrap_err(), "line 2: invalid number 'x'");
+
+        let backwards = CameraPath::from_text("1 0 0 0 0 0 0 1\n0.5 0 0 0 0 0 0 1\n");
+        assert_eq!(backwards.unwrap_err(), "line 2: time 0.5 does not increase");
+
+        let not_a_time = CameraPath::from_text("0 0 0 0 0 0 0 1\nNaN 0 0 0 0 0 0 1\n");
+        assert_eq!(not_a_time.unwrap_err(), "line 2: invalid number 'NaN'");
+
+        let no_rotation = CameraPath::from_text("0 0 0 0 0 0 0 0\n");
+        assert_eq!(no_rotation.unwrap_err(), "line 1: rotation has zero length");
+    }
+}
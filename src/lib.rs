@@ -0,0 +1,5 @@
+pub mod camera;
+pub mod math;
+pub mod obj;
+pub mod renderer;
+pub mod texture;
@@ -1,25 +1,25 @@
+
 use glam::Vec3;
 use glam::Vec2;
 
 use minifb::Window;
 
-mod math;
-use math::*;
-
-mod texture;
-use texture::*;
-
-mod camera;
-use camera::*;
-
-mod renderer;
-use renderer::*;
+use rusterizer_s::math::*;
+use rusterizer_s::texture::*;
+use rusterizer_s::camera::*;
+use rusterizer_s::camera::controller::CameraController;
+use rusterizer_s::renderer::*;
+use rusterizer_s::obj;
 
 
 
 const RESOLUTION_WIDTH: usize = 640; 
 const RESOLUTION_HEIGHT: usize = 480; 
 const UPSCALE: usize = 1;
+const SAMPLES: SampleCount = SampleCount::X4;
+
+//Camera paths are played back at this step whatever the frame rate, so every run renders the same frames
+const PLAYBACK_STEP: f32 = 1.0 / 60.0;
 
 const QUAD_INDICES: [usize; 6] = [
     0, 1, 2,
@@ -42,9 +42,11 @@ const QUAD_VERTEX_UVS: [Vec2; 4] = [
 
 
 fn create_window() -> minifb::Result<Window> {
-    let mut window_options = minifb::WindowOptions::default();
-    window_options.scale_mode = minifb::ScaleMode::Stretch;
-    window_options.resize = false;
+    let window_options = minifb::WindowOptions {
+        scale_mode: minifb::ScaleMode::Stretch,
+        resize: false,
+        ..Default::default()
+    };
 
     Window::new("Rasterizing with Rust", RESOLUTION_WIDTH * UPSCALE, RESOLUTION_HEIGHT * UPSCALE, window_options)
 }
@@ -53,35 +55,77 @@ fn main() {
     
     let mut window = create_window().unwrap();
     
+    let mut frame_buffer = FrameBuffer::new_multisampled(RESOLUTION_WIDTH, RESOLUTION_HEIGHT, SAMPLES);
     let mut output_surface = Texture::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
-    let mut depth_attachment = DepthTexture::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
 
     let mut timer = std::time::Instant::now();
 
     //Camera
-    let mut camera = Camera::default();
-    camera.position = Vec3::new(0.0, 0.0, 1.0);
-    camera.aspect_ratio = (RESOLUTION_WIDTH as f32) / (RESOLUTION_HEIGHT as f32);
-    camera.fov = std::f32::consts::PI * 0.25;
-    camera.near = 0.1;
-    camera.far = 10.0;
+    let mut camera = Camera {
+        position: Vec3::new(0.0, 0.0, 1.0),
+        aspect_ratio: (RESOLUTION_WIDTH as f32) / (RESOLUTION_HEIGHT as f32),
+        projection: Projection::Perspective { fov: std::f32::consts::PI * 0.25 },
+        near: 0.1,
+        far: 10.0,
+        ..Default::default()
+    };
 
     //Shader abstractions
-    let mut vs = vertex::VertexShader::default();
-    let mut fs = fragment::FragmentShader::default();
-    let mut ls = debug::DebugLineShader::default();
+    let mut vs = vertex::VertexShader::<programs::UnlitVertexProgram>::default();
+    let mut fs = fragment::FragmentShader::<programs::UnlitFragmentProgram>::default();
+    let render_state = state::RenderState::default();
+    let workers = std::thread::available_parallelism().map_or(1, |count| count.get());
 
     let texture = load_image_file(std::path::Path::new("assets/icon.png")).unwrap();
-    fs.mesh_texture = texture;
+    fs.uniforms.texture = texture;
 
     //Setting up vertices
-    let mut vertices = data::VertexInput::default();
-    vertices.positions = QUAD_VERTEX_POSITIONS.to_vec();
-    vertices.colours = QUAD_VERTEX_UVS.iter().map(|vec2|{ Vec3::new(vec2.x, vec2.y, 1.0) }).collect();
-    vertices.uvs = QUAD_VERTEX_UVS.to_vec();
+    let vertices = data::VertexInput {
+        positions: QUAD_VERTEX_POSITIONS.to_vec(),
+        colours: QUAD_VERTEX_UVS.iter().map(|vec2|{ Vec3::new(vec2.x, vec2.y, 1.0) }).collect(),
+        uvs: QUAD_VERTEX_UVS.to_vec(),
+        normals: vec![Vec3::Z; QUAD_VERTEX_POSITIONS.len()]
+    };
+
+    let mut mesh = data::Mesh::new(vertices, QUAD_INDICES.to_vec());
+
+    //The quad is drawn once per face of a cube
+    let mut model_matrices = vec![
+        glam::Mat4::IDENTITY,
+        glam::Mat4::from_rotation_y(std::f32::consts::PI * 0.5),
+        glam::Mat4::from_rotation_y(std::f32::consts::PI * 1.0),
+        glam::Mat4::from_rotation_y(std::f32::consts::PI * 1.5),
+        glam::Mat4::from_rotation_x(std::f32::consts::PI * 0.5),
+        glam::Mat4::from_rotation_x(std::f32::consts::PI * -0.5),
+    ];
+
+    let mut culler = culling::FrustumCuller::new(&camera);
+    let mut input = input::InputState::default();
+    let mut controller = controller::FirstPersonController::default();
+
+    //--record <file> saves the camera path of the session, --play <file> replays one instead of using the controls
+    let args: Vec<String> = std::env::args().collect();
+    let arg_path = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1)).map(std::path::PathBuf::from);
+
+    let record_path = arg_path("--record");
+    let playback = arg_path("--play").map(|path| path::CameraPath::load(&path).unwrap());
+    let mut recording = path::CameraPath::default();
+
+    //--model <file.obj> draws a mesh once instead of the quad, textured with the first diffuse map of its materials
+    if let Some(model_path) = arg_path("--model") {
+        let mut model = obj::load_obj_file(&model_path).unwrap();
+        if let Some(texture) = model.materials.iter_mut().find_map(|material| material.diffuse_texture.take()) {
+            fs.uniforms.texture = texture;
+        }
+        //OBJ uvs commonly tile past [0, 1]
+        fs.uniforms.sampler.address_mode = AddressMode::Repeat;
+
+        mesh = data::Mesh::new(model.vertices, model.indices);
+        model_matrices = vec![glam::Mat4::IDENTITY];
+    }
 
-    let indices = QUAD_INDICES.to_owned();
-    let mut prev_mouse = Vec2::default();
+    let mut elapsed = 0.0;
+    let mut frame = 0;
 
     while window.is_open() {
 
@@ -89,44 +133,49 @@ fn main() {
         let dt = timer.elapsed().as_secs_f32();
         timer = std::time::Instant::now(); //reset timer
 
-        //Mouse delta
-        let mut mouse_delta = Vec2::default();
-        if let Some((x, y)) = window.get_mouse_pos(minifb::MouseMode::Discard) {
-                
-            let mouse_pos = Vec2::new(x, y);
-            mouse_delta = mouse_pos - prev_mouse;
-            prev_mouse = mouse_pos;
+        //camera controls
+        if let Some(playback) = &playback {
+            let time = frame as f32 * PLAYBACK_STEP;
+            if playback.keyframes.is_empty() || time > playback.duration() { break; }
+            playback.apply(playback.keyframes[0].time + time, &mut camera);
+        } else {
+            input.poll_window(&window);
+            controller.update(&mut camera, &input, dt);
+            input.end_frame();
         }
 
-        //camera controls
-        first_person_controls(&mut camera, &window, mouse_delta, dt);
+        if record_path.is_some() { recording.record(elapsed, &camera); }
+        elapsed += dt;
+        frame += 1;
+
         let (view, projection) = camera.generate_view_projection();
-        vs.view = view;
-        vs.projection = projection;
+        vs.uniforms.view = view;
+        vs.uniforms.projection = projection;
+        vs.clip.set_camera(&camera);
 
         //clear
-        output_surface.clear(colour::f32_to_hex(1.0, 0.0, 0.0, 0.0));
-        depth_attachment.clear(1.0);
+        frame_buffer.clear(colour::f32_to_hex(1.0, 0.0, 0.0, 0.0), camera.far_depth(), 0);
 
         //draw
 
-        let model_matrices = [
-            glam::Mat4::IDENTITY,
-            glam::Mat4::from_rotation_y(std::f32::consts::PI * 0.5),
-            glam::Mat4::from_rotation_y(std::f32::consts::PI * 1.0),
-            glam::Mat4::from_rotation_y(std::f32::consts::PI * 1.5),
-            glam::Mat4::from_rotation_x(std::f32::consts::PI * 0.5),
-            glam::Mat4::from_rotation_x(std::f32::consts::PI * -0.5),
-        ];
-
-        for model in model_matrices { 
-            vs.model = model;  
-            let (t, i) = vs.dispatch(&vertices, &indices);
-            fs.dispatch(&mut output_surface, &mut depth_attachment, &t, &i);
+        culler.update(&camera);
+
+        for model in &model_matrices { 
+            if culler.cull(&mesh.bounds, model) { continue; }
+
+            vs.uniforms.model = *model;
+            let (t, i) = vs.dispatch(&render_state, primitive::PrimitiveTopology::TriangleList, &mesh.vertices, &mesh.indices);
+            fs.dispatch_parallel(&render_state, &mut frame_buffer, &t, &i, workers);
         }
 
+        window.set_title(&format!("Rasterizing with Rust - {} of {} draws culled", culler.stats.culled, culler.stats.tested()));
+
+        frame_buffer.resolve(&mut output_surface);
         window.update_with_buffer(output_surface.as_slice(), RESOLUTION_WIDTH, RESOLUTION_HEIGHT).unwrap();
         //dbg!(dt);
     }
 
+    if let Some(path) = record_path {
+        recording.save(&path).unwrap();
+    }
 }
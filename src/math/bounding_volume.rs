@@ -0,0 +1,151 @@
+use glam::Mat4;
+use glam::Vec3;
+
+use super::plane::Plane;
+
+//Result of testing a volume against a set of planes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Containment {
+    Outside,
+    Intersect,
+    Inside
+}
+
+//Axis aligned bounding box
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    //None for an empty point set
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let first = *points.first()?;
+        Some(points.iter().fold(Self::new(first, first), |aabb, point| Self::new(aabb.min.min(*point), aabb.max.max(*point))))
+    }
+
+    pub fn centre(&self) -> Vec3 { (self.min + self.max) * 0.5 }
+    pub fn extents(&self) -> Vec3 { (self.max - self.min) * 0.5 }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| Vec3::new(
+            if corner & 1 == 0 { self.min.x } else { self.max.x },
+            if corner & 2 == 0 { self.min.y } else { self.max.y },
+            if corner & 4 == 0 { self.min.z } else { self.max.z }
+        ))
+    }
+
+    //Box around the transformed box, can only grow
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        let corners = self.corners().map(|corner| transform.transform_point3(corner));
+        Self::from_points(&corners).unwrap_or_default()
+    }
+
+    //Planes face inwards, only the corner furthest along and the one furthest against each normal are tested
+    pub fn classify(&self, planes: &[Plane]) -> Containment {
+        let (centre, extents) = (self.centre(), self.extents());
+        let mut result = Containment::Inside;
+
+        for plane in planes {
+            let distance = plane.signed_distance(centre);
+            let radius = extents.dot(plane.normal().abs());
+
+            if distance < -radius { return Containment::Outside; }
+            if distance < radius { result = Containment::Intersect; }
+        }
+
+        result
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub centre: Vec3,
+    pub radius: f32
+}
+
+impl BoundingSphere {
+
+    pub fn new(centre: Vec3, radius: f32) -> Self {
+        Self { centre, radius }
+    }
+
+    //Centred on the box of the points, not the smallest sphere but close for most meshes
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let centre = Aabb::from_points(points)?.centre();
+        let radius = points.iter().map(|point| point.distance(centre)).fold(0.0, f32::max);
+        Some(Self::new(centre, radius))
+    }
+
+    //Radius grows with the largest scale of the transform
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        let scale = transform.x_axis.truncate().length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+
+        Self::new(transform.transform_point3(self.centre), self.radius * scale)
+    }
+
+    pub fn classify(&self, planes: &[Plane]) -> Containment {
+        let mut result = Containment::Inside;
+
+        for plane in planes {
+            let distance = plane.signed_distance(self.centre);
+
+            if distance < -self.radius { return Containment::Outside; }
+            if distance < self.radius { result = Containment::Intersect; }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+    use crate::math::plane::Plane;
+    use super::{Aabb, BoundingSphere, Containment};
+
+    //Unit cube around the origin
+    fn cube() -> Vec<Plane> {
+        [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z].iter()
+            .map(|normal| Plane::new(*normal, -1.0))
+            .collect()
+    }
+
+    #[test]
+    fn boxes_classify_against_planes() {
+        let aabb = Aabb::from_points(&[Vec3::splat(-0.5), Vec3::splat(0.5)]).unwrap();
+        assert_eq!(aabb.classify(&cube()), Containment::Inside);
+
+        let moved = aabb.transform(&Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(moved.classify(&cube()), Containment::Intersect);
+
+        let far = aabb.transform(&Mat4::from_translation(Vec3::new(3.0, 0.0, 0.0)));
+        assert_eq!(far.classify(&cube()), Containment::Outside);
+
+        //Rotating grows the box
+        let rotated = aabb.transform(&Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4));
+        assert!((rotated.max.x - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn spheres_classify_against_planes() {
+        let sphere = BoundingSphere::from_points(&[Vec3::new(-0.5, 0.0, 0.0), Vec3::new(0.5, 0.0, 0.0)]).unwrap();
+        assert_eq!(sphere.radius, 0.5);
+        assert_eq!(sphere.classify(&cube()), Containment::Inside);
+
+        let scaled = sphere.transform(&Mat4::from_scale(Vec3::new(1.0, 3.0, 1.0)));
+        assert_eq!(scaled.radius, 1.5);
+        assert_eq!(scaled.classify(&cube()), Containment::Intersect);
+
+        let far = sphere.transform(&Mat4::from_translation(Vec3::new(0.0, 0.0, -2.0)));
+        assert_eq!(far.classify(&cube()), Containment::Outside);
+    }
+}
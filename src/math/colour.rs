@@ -28,9 +28,9 @@ pub fn vec4_to_hex(v: glam::Vec4) -> u32 {
 //ARGB -> RGBA
 pub fn hex_to_f32(hex: u32) -> glam::Vec4 {
     glam::Vec4::new(
-        u8_to_f32((hex) as u8),
-        u8_to_f32((hex >> 8) as u8),
         u8_to_f32((hex >> 16) as u8),
+        u8_to_f32((hex >> 8) as u8),
+        u8_to_f32((hex) as u8),
         u8_to_f32((hex >> 24) as u8)
     )
 }
\ No newline at end of file
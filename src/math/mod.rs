@@ -1,12 +1,14 @@
-use glam::Mat4;
-use glam::Quat;
 use glam::Vec2;
 use glam::Vec3;
 use glam::Vec4;
 
 pub mod bounding_box;
+pub mod bounding_volume;
 pub mod colour;
 pub mod plane;
+pub mod varying;
+
+use varying::Varying;
 
 pub fn cull_back_face(v1: Vec3, v2: Vec3, v3: Vec3) -> bool {
 
@@ -18,62 +20,75 @@ pub fn cull_back_face(v1: Vec3, v2: Vec3, v3: Vec3) -> bool {
     normal.dot(-Vec3::Z) >= 0.0
 }
 
-pub fn homogenous_clip(a: Vec4, b: Vec4, plane: Vec4) -> Option<f32> {
-    let line_vector = b - a;
+//Near goes first so every later plane only sees vertices in front of the eye
+//With reversed-Z near and far swap, 0 <= z <= w holds either way
+pub const CLIP_PLANES: [Vec4; 6] = [
+    Vec4::new(0.0, 0.0, 1.0, 0.0), //Near
+    Vec4::new(1.0, 0.0, 0.0, 1.0), //Left
+    Vec4::new(-1.0, 0.0, 0.0, 1.0), //Right
+    Vec4::new(0.0, 1.0, 0.0, 1.0), //Bottom
+    Vec4::new(0.0, -1.0, 0.0, 1.0), //Top
+    Vec4::new(0.0, 0.0, -1.0, 1.0), //Far
+];
+
+//Side planes are pushed out to this many times the clip volume by default, the rasterizer already
+//bounds everything to the screen so only triangles reaching past it have to be split
+pub const GUARD_BAND: f32 = 16.0;
+
+//Largest guard band the clipper uses, anything wider is clamped to it
+//Snapped coordinates then stay small enough for the i64 edge equations of the rasterizer on targets up to 8192 pixels
+pub const MAX_GUARD_BAND: f32 = 1024.0;
+
+//CLIP_PLANES with the side planes at guard_band times the clip volume, 1.0 clips exactly to the screen
+pub fn guard_band_planes(guard_band: f32) -> [Vec4; 6] {
+    let mut planes = CLIP_PLANES;
+    for plane in &mut planes[1..5] {
+        plane.w *= guard_band;
+    }
+    planes
+}
 
-    let div = plane.dot(line_vector);
-    if div.abs() < std::f32::EPSILON { return None; }
+//Clips a clip space segment and its varyings against the planes, None if nothing of it is left
+pub fn clip_homogenous_line<V: Varying>(start: (Vec4, V), end: (Vec4, V), planes: &[Vec4]) -> Option<[(Vec4, V); 2]> {
+    clip_line_by(start, end, planes.len(), |vertex, plane| planes[plane].dot(vertex.0), split_homogenous)
+}
 
-    let t = -plane.dot(a) / div;
-    if t > 0.0 && t < 1.0 { Some(t) }
-    else { None }
+//Point at t along a clip space edge, varyings are blended with the weights of that edge
+pub fn split_homogenous<V: Varying>(start: &(Vec4, V), end: &(Vec4, V), t: f32) -> (Vec4, V) {
+    let weights = varying::Weights::along_edge(t, start.0.w, end.0.w);
+    (lerp(start.0, end.0, t), V::lerp(start.1, end.1, &weights))
 }
 
-pub fn clip_homogenous_triangle(vertices: &[Vec4; 3]) -> Vec<(Vec4, Vec3)> {
-
-    let mut output_list: Vec<(Vec4, Vec3)> = vertices.iter()
-        .enumerate()
-        .map(|(i, v)| {
-            if i == 0 {
-                (v.clone(), Vec3::X)
-            } else if i == 1 {
-                (v.clone(), Vec3::Y)
-            } else {
-                (v.clone(), Vec3::Z)
-            }
-        })
-        .collect();
+//Sutherland-Hodgman over plane_count planes, distance(vertex, plane) is >= 0 on the inner side and has to be
+//linear along edges, split(start, end, t) builds the vertex at t along an edge
+pub fn clip_polygon_by<T, D, S>(vertices: &[T], plane_count: usize, distance: D, split: S) -> Vec<T>
+where T: Copy, D: Fn(&T, usize) -> f32, S: Fn(&T, &T, f32) -> T
+{
+    let mut output_list: Vec<T> = vertices.to_vec();
 
-    let clip_planes = [
-        Vec4::new(1.0, 0.0, 0.0, 1.0), //Left
-        Vec4::new(-1.0, 0.0, 0.0, 1.0), //Right
-        Vec4::new(0.0, 1.0, 0.0, 1.0), //Bottom
-        Vec4::new(0.0, -1.0, 0.0, 1.0), //Top
-        Vec4::new(0.0, 0.0, 1.0, 0.0), //Near
-        Vec4::new(0.0, 0.0, -1.0, 1.0), //Far
-    ];
+    for plane in 0..plane_count {
 
-    for plane in clip_planes {
+        if output_list.is_empty() { break; }
 
         let input_list = output_list.clone();
         output_list.clear();
 
         for i in 0..input_list.len() {
 
-            let current_point = input_list[i];
-            let next_point = input_list[(i+1) % input_list.len()];
+            let current_point = &input_list[i];
+            let next_point = &input_list[(i+1) % input_list.len()];
 
-            let current_inside = plane.dot(current_point.0) >= 0.0;
+            let current_distance = distance(current_point, plane);
+            let next_distance = distance(next_point, plane);
 
-            if current_inside {
-                output_list.push(current_point);
+            if current_distance >= 0.0 {
+                output_list.push(*current_point);
             }
 
-            if let Some(t) = homogenous_clip(current_point.0, next_point.0, plane) {
-
-                let interpolated = lerp(current_point.0, next_point.0, t);
-                let barycentric_coords =  lerp(current_point.1, next_point.1, t);
-                output_list.push((interpolated, barycentric_coords));
+            //Only edges strictly crossing the plane get a new vertex
+            if (current_distance > 0.0 && next_distance < 0.0) || (current_distance < 0.0 && next_distance > 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                output_list.push(split(current_point, next_point, t));
             }
         }
     }
@@ -81,35 +96,56 @@ pub fn clip_homogenous_triangle(vertices: &[Vec4; 3]) -> Vec<(Vec4, Vec3)> {
     output_list
 }
 
-pub fn barycentric_lerp<T>(weights: Vec3, v1: T, v2: T, v3: T) -> T 
-where T: std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>
+//Segment version of clip_polygon_by, None if nothing of it is left
+pub fn clip_line_by<T, D, S>(start: T, end: T, plane_count: usize, distance: D, split: S) -> Option<[T; 2]>
+where D: Fn(&T, usize) -> f32, S: Fn(&T, &T, f32) -> T
 {
-    (v1 * weights.x) + (v2 * weights.y) + (v3 * weights.z)
-}
+    //Portion of the segment that is kept
+    let mut t_start: f32 = 0.0;
+    let mut t_end: f32 = 1.0;
 
-pub fn should_cull_triangle(v1: Vec4, v2: Vec4, v3: Vec4) -> bool {
+    for plane in 0..plane_count {
+        let start_distance = distance(&start, plane);
+        let end_distance = distance(&end, plane);
 
-    // cull tests against the 6 planes
-    if v1.x > v1.w && v2.x > v2.w && v3.x > v3.w { return true; }
-    if v1.x < -v1.w && v2.x < -v2.w && v3.x < -v3.w { return true; }
+        if start_distance < 0.0 && end_distance < 0.0 { return None; }
 
-    if v1.y > v1.w && v2.y > v2.w && v3.y > v3.w { return true; }
-    if v1.y < -v1.w && v2.y < -v2.w && v3.y < -v3.w { return true; }
-    
-    if v1.z > v1.w && v2.z > v2.w && v3.z > v3.w { return true; }
-    if v1.z < 0.0 && v2.z < 0.0 && v3.z < 0.0 { return true; }
+        if start_distance < 0.0 {
+            t_start = t_start.max(start_distance / (start_distance - end_distance));
+        } else if end_distance < 0.0 {
+            t_end = t_end.min(start_distance / (start_distance - end_distance));
+        }
+    }
+
+    if t_start > t_end { return None; }
 
-    false
+    Some([split(&start, &end, t_start), split(&start, &end, t_end)])
 }
 
-pub fn barycentric_weights(point: Vec2, edge_1: Vec2, edge_2: Vec2, edge_3: Vec2) -> Option<Vec3> {
+pub fn barycentric_lerp<T>(weights: Vec3, v1: T, v2: T, v3: T) -> T 
+where T: std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>
+{
+    (v1 * weights.x) + (v2 * weights.y) + (v3 * weights.z)
+}
+
+//True if every vertex lies on the outer side of one of the planes
+pub fn outside_any_plane(vertices: &[Vec4], planes: &[Vec4]) -> bool {
+    planes.iter().any(|plane| vertices.iter().all(|v| plane.dot(*v) < 0.0))
+}
 
-    let bary = Vec3::new(
+pub fn barycentric_coordinates(point: Vec2, edge_1: Vec2, edge_2: Vec2, edge_3: Vec2) -> Vec3 {
+    Vec3::new(
         edge_function(point, edge_2, edge_3),
         edge_function(point, edge_3, edge_1),
         edge_function(point, edge_1, edge_2))
         / edge_function(edge_1, edge_2, edge_3
-    );
+    )
+}
+
+//Floating point reference the fixed point rasterizer is tested against
+pub fn barycentric_weights(point: Vec2, edge_1: Vec2, edge_2: Vec2, edge_3: Vec2) -> Option<Vec3> {
+
+    let bary = barycentric_coordinates(point, edge_1, edge_2, edge_3);
     
     if bary.x >= 0.0 && bary.y >= 0.0 && bary.z >= 0.0 { Some(bary) }
     else { None }
@@ -123,23 +159,12 @@ pub fn edge_function(p: Vec2, a: Vec2, b: Vec2) -> f32 {
 }
 
 pub fn generate_triangle_bounding_box(v1: Vec2, v2: Vec2, v3: Vec2) -> bounding_box::BoundingBox {
-    let v_max = v1.max(v2).max(v3).round();
-    let v_min = v1.min(v2).min(v3).round();
+    let v_max = v1.max(v2).max(v3).ceil();
+    let v_min = v1.min(v2).min(v3).floor();
     
     bounding_box::BoundingBox { start: v_min.as_uvec2(), end: v_max.as_uvec2() }
 }
 
-pub fn triangle_in_bounds(v1: Vec4, v2: Vec4, v3: Vec4) -> bool {
-
-    let in_range = |v: Vec4| -> bool {
-        v.x > -v.w && v.x < v.w &&
-        v.y > -v.w && v.y < v.w &&
-        v.z >  0.0 && v.z < v.w
-    };
-
-    in_range(v1) || in_range(v2) || in_range(v3)
-}
-
 pub fn lerp<T>(start: T, end: T, alpha: f32) -> T
 where T: std::ops::Sub<Output = T> + std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T> + Copy
 {
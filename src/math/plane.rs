@@ -1,4 +1,6 @@
-use glam::{Vec3, Vec4};
+use glam::Mat4;
+use glam::Vec3;
+use glam::Vec4;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Plane {
@@ -16,49 +18,27 @@ impl Plane {
         Plane::new(normal, point.dot(normal))
     }
 
+    pub fn normal(&self) -> Vec3 { self.normal }
+
     pub fn signed_distance(&self, point: Vec3) -> f32 {
-        let res = self.normal.dot(point) - self.d;
-        res
+        self.normal.dot(point) - self.d
     }
 
-    pub fn intersect(&self, start: Vec3, end: Vec3) -> Option<f32> {
-
-        let line_dir = end - start;
-
-        let normal_dir_dot = self.normal.dot(line_dir);
-        if normal_dir_dot == 0.0 { return None; }
+    //Inverse of to_vec4, normalizes the plane, None if it has no direction
+    pub fn from_vec4(v: Vec4) -> Option<Self> {
+        let length = v.truncate().length();
+        if length <= f32::EPSILON { return None; }
 
-        let t = (self.d - self.normal.dot(start)) / normal_dir_dot;
-
-        if t >= 0.0 && t <= 1.0 { Some(t) }
-        else { None }
+        Some(Plane::new(v.truncate() / length, -v.w / length))
     }
-}
-
-pub fn clip_polygon(polygon: &[Vec3], frustum: &[Plane]) -> Vec<Vec3> {
 
-    let mut output_list = polygon.to_owned();
-
-    for plane in frustum {
-        let input_list = output_list.clone();
-    
-        output_list.clear();
-
-        for i in 0..input_list.len() {
-
-            let current_point = input_list[i];
-            let next_point = input_list[(i+1) % input_list.len()];
-
-            let current_inside = plane.signed_distance(current_point) >= 0.0;
-           
-            if current_inside {
-                output_list.push(current_point);
-            }
+    //Plane as a vector whose dot product with a homogenous point is its signed distance
+    pub fn to_vec4(self) -> Vec4 {
+        self.normal.extend(-self.d)
+    }
 
-            if let Some(t) = plane.intersect(current_point, next_point) {
-                output_list.push(super::lerp(current_point, next_point, t));
-            }
-        }
+    //Same plane in the clip space of a transform, e.g. projection * view for a world space plane
+    pub fn to_clip_space(self, transform: &Mat4) -> Vec4 {
+        transform.inverse().transpose() * self.to_vec4()
     }
-    output_list
-}
\ No newline at end of file
+}
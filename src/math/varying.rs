@@ -0,0 +1,174 @@
+use glam::Vec3;
+
+//Blend weights of a sample, smooth varyings use the perspective correct set and noperspective ones the screen linear set
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Weights<T> {
+    pub perspective: T,
+    pub linear: T
+}
+
+impl Weights<f32> {
+
+    //Weights for a point at alpha along a clip space edge, start_w and end_w are the clip space w of the end points
+    pub fn along_edge(alpha: f32, start_w: f32, end_w: f32) -> Self {
+
+        //Points behind the eye have no screen position, fall back to the clip space blend
+        if start_w <= 0.0 || end_w <= 0.0 {
+            return Self { perspective: alpha, linear: alpha };
+        }
+
+        let w = super::lerp(start_w, end_w, alpha);
+        Self { perspective: alpha, linear: alpha * end_w / w }
+    }
+}
+
+impl Weights<Vec3> {
+
+    //screen_weights are the barycentric weights in screen space, inv_w holds 1/w of each corner
+    pub fn from_screen(screen_weights: Vec3, inv_w: Vec3) -> Self {
+        let perspective = screen_weights * inv_w;
+        Self { perspective: perspective / (perspective.x + perspective.y + perspective.z), linear: screen_weights }
+    }
+}
+
+//Anything the clipper and the rasterizer can interpolate across a primitive
+//Plain types are smooth (perspective correct), wrap them in Flat or NoPerspective to change the qualifier
+pub trait Varying: Copy {
+    //Blend along a clipped edge
+    fn lerp(start: Self, end: Self, alpha: &Weights<f32>) -> Self;
+
+    //Blend of the three triangle corners, v1 is the provoking vertex
+    fn interpolate(v1: Self, v2: Self, v3: Self, weights: &Weights<Vec3>) -> Self;
+
+    //Takes the flat qualified values from the provoking vertex, used on vertices generated by clipping
+    fn provoke(self, provoking: &Self) -> Self;
+}
+
+//Scalars, glam vectors and any type with the same bounds as barycentric_lerp
+impl<T> Varying for T
+where T: Copy + std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>
+{
+    fn lerp(start: Self, end: Self, alpha: &Weights<f32>) -> Self {
+        start * (1.0 - alpha.perspective) + end * alpha.perspective
+    }
+
+    fn interpolate(v1: Self, v2: Self, v3: Self, weights: &Weights<Vec3>) -> Self {
+        super::barycentric_lerp(weights.perspective, v1, v2, v3)
+    }
+
+    fn provoke(self, _provoking: &Self) -> Self { self }
+}
+
+//Not interpolated, every fragment gets the value of the provoking vertex
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flat<T>(pub T);
+
+impl<T: Copy> Varying for Flat<T> {
+    fn lerp(start: Self, _end: Self, _alpha: &Weights<f32>) -> Self { start }
+
+    fn interpolate(v1: Self, _v2: Self, _v3: Self, _weights: &Weights<Vec3>) -> Self { v1 }
+
+    fn provoke(self, provoking: &Self) -> Self { *provoking }
+}
+
+//Interpolated linearly in screen space, without perspective correction
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NoPerspective<T>(pub T);
+
+impl<T: Varying> Varying for NoPerspective<T> {
+    fn lerp(start: Self, end: Self, alpha: &Weights<f32>) -> Self {
+        let linear = Weights { perspective: alpha.linear, linear: alpha.linear };
+        Self(T::lerp(start.0, end.0, &linear))
+    }
+
+    fn interpolate(v1: Self, v2: Self, v3: Self, weights: &Weights<Vec3>) -> Self {
+        let linear = Weights { perspective: weights.linear, linear: weights.linear };
+        Self(T::interpolate(v1.0, v2.0, v3.0, &linear))
+    }
+
+    fn provoke(self, provoking: &Self) -> Self {
+        Self(self.0.provoke(&provoking.0))
+    }
+}
+
+//Implements Varying for a struct by interpolating each listed field on its own
+//e.g. impl_varying!(MyVaryings { normal, world_position, uv });
+macro_rules! impl_varying {
+    ($name:ident { $($field:ident),+ $(,)? }) => {
+        impl $crate::math::varying::Varying for $name {
+            fn lerp(start: Self, end: Self, alpha: &$crate::math::varying::Weights<f32>) -> Self {
+                Self { $($field: $crate::math::varying::Varying::lerp(start.$field, end.$field, alpha)),+ }
+            }
+
+            fn interpolate(v1: Self, v2: Self, v3: Self, weights: &$crate::math::varying::Weights<glam::Vec3>) -> Self {
+                Self { $($field: $crate::math::varying::Varying::interpolate(v1.$field, v2.$field, v3.$field, weights)),+ }
+            }
+
+            fn provoke(self, provoking: &Self) -> Self {
+                Self { $($field: $crate::math::varying::Varying::provoke(self.$field, &provoking.$field)),+ }
+            }
+        }
+    };
+}
+
+pub(crate) use impl_varying;
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use super::{Flat, NoPerspective, Varying, Weights};
+
+    #[derive(Clone, Copy)]
+    struct Attributes {
+        normal: Vec3,
+        uv: Vec2,
+        screen: NoPerspective<f32>,
+        id: Flat<u32>
+    }
+
+    super::impl_varying!(Attributes { normal, uv, screen, id });
+
+    #[test]
+    fn struct_fields_interpolate_independently() {
+        let a = Attributes { normal: Vec3::X, uv: Vec2::ZERO, screen: NoPerspective(0.0), id: Flat(1) };
+        let b = Attributes { normal: Vec3::Y, uv: Vec2::ONE, screen: NoPerspective(1.0), id: Flat(2) };
+
+        let mid = Attributes::lerp(a, b, &Weights::along_edge(0.5, 1.0, 1.0));
+        assert_eq!(mid.normal, Vec3::new(0.5, 0.5, 0.0));
+        assert_eq!(mid.uv, Vec2::splat(0.5));
+        assert_eq!(mid.screen.0, 0.5);
+
+        let corner = Attributes::interpolate(b, a, a, &Weights { perspective: Vec3::Z, linear: Vec3::Z });
+        assert_eq!(corner.normal, Vec3::X);
+        assert_eq!(corner.id, Flat(2));
+    }
+
+    #[test]
+    fn qualifiers_pick_their_weights() {
+        //Far corner at w = 3, so the screen midpoint is only a quarter of the way in clip space
+        let weights = Weights::<Vec3>::from_screen(Vec3::new(0.5, 0.5, 0.0), Vec3::new(1.0, 1.0 / 3.0, 1.0));
+
+        let smooth = f32::interpolate(0.0, 1.0, 0.0, &weights);
+        let linear = NoPerspective::interpolate(NoPerspective(0.0), NoPerspective(1.0), NoPerspective(0.0), &weights);
+
+        assert!((smooth - 0.25).abs() < 1e-6);
+        assert!((linear.0 - 0.5).abs() < 1e-6);
+
+        let edge = Weights::along_edge(0.25, 1.0, 3.0);
+        assert!((edge.linear - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn flat_takes_provoking_vertex() {
+        let clipped = Attributes {
+            normal: Vec3::Z, uv: Vec2::ONE, screen: NoPerspective(0.3), id: Flat(7)
+        };
+        let provoking = Attributes {
+            normal: Vec3::X, uv: Vec2::ZERO, screen: NoPerspective(0.0), id: Flat(3)
+        };
+
+        let result = clipped.provoke(&provoking);
+        assert_eq!(result.id, Flat(3));
+        assert_eq!(result.normal, Vec3::Z);
+    }
+}
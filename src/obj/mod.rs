@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use glam::Vec2;
+use glam::Vec3;
+
+use crate::renderer::data::VertexInput;
+use crate::texture;
+use crate::texture::Texture;
+
+//Surface description from an MTL file
+pub struct Material {
+    pub name: String,
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub shininess: f32,
+    //Opacity, 1 is opaque
+    pub dissolve: f32,
+    pub diffuse_texture: Option<Texture>
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ambient: Vec3::ZERO,
+            diffuse: Vec3::ONE,
+            specular: Vec3::ZERO,
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_texture: None
+        }
+    }
+}
+
+//Run of triangles sharing a group name and a material
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjGroup {
+    pub name: String,
+    //Into ObjModel::materials
+    pub material: Option<usize>,
+    //Into ObjModel::indices
+    pub indices: Range<usize>
+}
+
+//Triangle list ready for VertexShader::dispatch, vertex colours hold the diffuse colour of the material
+#[derive(Default)]
+pub struct ObjModel {
+    pub vertices: VertexInput,
+    pub indices: Vec<usize>,
+    pub groups: Vec<ObjGroup>,
+    pub materials: Vec<Material>
+}
+
+//Loads an OBJ file with the MTL libraries it references, paths inside it are relative to the file
+pub fn load_obj_file(path: &Path) -> Result<ObjModel, String> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let text = std::fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+
+    parse_obj(&text, |library| load_mtl_file(&directory.join(library)))
+        .map_err(|error| format!("{}: {error}", path.display()))
+}
+
+//Loads an MTL file, textures are resolved relative to it
+pub fn load_mtl_file(path: &Path) -> Result<Vec<Material>, String> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let text = std::fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+
+    parse_mtl(&text, |file| texture::load_image_file(&directory.join(file)))
+        .map_err(|error| format!("{}: {error}", path.display()))
+}
+
+fn parse_floats<const N: usize>(values: &[&str], number: usize) -> Result<[f32; N], String> {
+    if values.len() < N {
+        return Err(format!("line {number}: expected {N} values, found {}", values.len()));
+    }
+
+    let mut out = [0.0; N];
+    for (out, value) in out.iter_mut().zip(values) {
+        *out = value.parse().map_err(|_| format!("line {number}: invalid number '{value}'"))?;
+    }
+    Ok(out)
+}
+
+//1 based, negative values count back from the last element read so far
+fn resolve_index(value: &str, count: usize, number: usize) -> Result<usize, String> {
+    let index: i64 = value.parse().map_err(|_| format!("line {number}: invalid index '{value}'"))?;
+
+    let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("line {number}: index {index} out of range"));
+    }
+    Ok(resolved as usize)
+}
+
+//Position, uv, normal and material of a face corner, corners with the same key share an output vertex
+type VertexKey = (usize, Option<usize>, Option<usize>, Option<usize>);
+
+//Lines split into their keyword and values, comments and blank lines removed
+fn statements(text: &str) -> impl Iterator<Item = (usize, &str, Vec<&str>)> {
+    text.lines().enumerate().filter_map(|(index, line)| {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next()?;
+        Some((index + 1, keyword, tokens.collect()))
+    })
+}
+
+//load_mtl is called with every library named by mtllib
+pub fn parse_obj<F>(text: &str, mut load_mtl: F) -> Result<ObjModel, String>
+where F: FnMut(&str) -> Result<Vec<Material>, String>
+{
+    let mut model = ObjModel::default();
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec2> = Vec::new();
+
+    let mut emitted: HashMap<VertexKey, usize> = HashMap::new();
+
+    let mut group_name = String::from("default");
+    let mut material: Option<usize> = None;
+
+    for (number, keyword, values) in statements(text) {
+        match keyword {
+            "v" => positions.push(Vec3::from(parse_floats::<3>(&values, number)?)),
+            "vn" => normals.push(Vec3::from(parse_floats::<3>(&values, number)?)),
+            //v defaults to 0 and the optional w is not used
+            "vt" => {
+                if values.is_empty() || values.len() > 3 {
+                    return Err(format!("line {number}: expected 1 to 3 values, found {}", values.len()));
+                }
+                let [u] = parse_floats::<1>(&values, number)?;
+                let v = if values.len() > 1 { parse_floats::<1>(&values[1..], number)?[0] } else { 0.0 };
+                uvs.push(Vec2::new(u, v));
+            }
+            "g" | "o" => {
+                group_name = values.join(" ");
+                start_group(&mut model, &group_name, material);
+            }
+            "usemtl" => {
+                let name = values.join(" ");
+                material = Some(model.materials.iter().position(|material| material.name == name)
+                    .ok_or_else(|| format!("line {number}: unknown material '{name}'"))?);
+                start_group(&mut model, &group_name, material);
+            }
+            "mtllib" => {
+                for library in values {
+                    model.materials.extend(load_mtl(library).map_err(|error| format!("line {number}: {error}"))?);
+                }
+            }
+            "f" => {
+                if values.len() < 3 {
+                    return Err(format!("line {number}: face needs at least 3 vertices, found {}", values.len()));
+                }
+
+                let mut face = Vec::with_capacity(values.len());
+                for value in &values {
+                    //v, v/vt, v//vn or v/vt/vn
+                    let mut parts = value.split('/');
+                    let position = resolve_index(parts.next().unwrap_or(""), positions.len(), number)?;
+                    let uv = match parts.next() {
+                        Some(part) if !part.is_empty() => Some(resolve_index(part, uvs.len(), number)?),
+                        _ => None
+                    };
+                    let normal = match parts.next() {
+                        Some(part) if !part.is_empty() => Some(resolve_index(part, normals.len(), number)?),
+                        _ => None
+                    };
+
+                    let key = (position, uv, normal, material);
+                    let index = *emitted.entry(key).or_insert_with(|| {
+                        let vertices = &mut model.vertices;
+                        vertices.positions.push(positions[position]);
+                        vertices.uvs.push(uv.map_or(Vec2::ZERO, |uv| uvs[uv]));
+                        vertices.normals.push(normal.map_or(Vec3::ZERO, |normal| normals[normal]));
+                        vertices.colours.push(material.map_or(Vec3::ONE, |material| model.materials[material].diffuse));
+                        vertices.positions.len() - 1
+                    });
+                    face.push(index);
+                }
+
+                if model.groups.is_empty() {
+                    start_group(&mut model, &group_name, material);
+                }
+
+                //Fan triangulation, faces are expected to be convex
+                for k in 1..face.len() - 1 {
+                    model.indices.extend([face[0], face[k], face[k + 1]]);
+                }
+                if let Some(group) = model.groups.last_mut() {
+                    group.indices.end = model.indices.len();
+                }
+            }
+            //Smoothing groups, lines and the rest are not used by the renderer
+            _ => ()
+        }
+    }
+
+    //Groups that never got a face
+    model.groups.retain(|group| !group.indices.is_empty());
+    Ok(model)
+}
+
+fn start_group(model: &mut ObjModel, name: &str, material: Option<usize>) {
+    let start = model.indices.len();
+
+    //Replaces the previous group if nothing was added to it
+    if model.groups.last().is_some_and(|group| group.indices.is_empty()) {
+        model.groups.pop();
+    }
+
+    model.groups.push(ObjGroup { name: name.to_string(), material, indices: start..start });
+}
+
+//load_texture is called with the file name of every texture map
+pub fn parse_mtl<F>(text: &str, mut load_texture: F) -> Result<Vec<Material>, String>
+where F: FnMut(&str) -> Result<Texture, String>
+{
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (number, keyword, values) in statements(text) {
+        if keyword == "newmtl" {
+            materials.push(Material { name: values.join(" "), ..Default::default() });
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            return Err(format!("line {number}: '{keyword}' before any newmtl"));
+        };
+
+        match keyword {
+            "Ka" => material.ambient = Vec3::from(parse_floats::<3>(&values, number)?),
+            "Kd" => material.diffuse = Vec3::from(parse_floats::<3>(&values, number)?),
+            "Ks" => material.specular = Vec3::from(parse_floats::<3>(&values, number)?),
+            "Ns" => material.shininess = parse_floats::<1>(&values, number)?[0],
+            "d" => material.dissolve = parse_floats::<1>(&values, number)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats::<1>(&values, number)?[0],
+            "map_Kd" => {
+                //Options come before the file name
+                let file = values.last().ok_or_else(|| format!("line {number}: map_Kd without a file"))?;
+                material.diffuse_texture = Some(load_texture(file).map_err(|error| format!("line {number}: {error}"))?);
+            }
+            _ => ()
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use crate::texture::Texture;
+    use super::{parse_mtl, parse_obj, Material};
+
+    const MTL: &str = "
+        newmtl red
+        Kd 1 0 0
+        map_Kd -s 1 1 1 red.png
+
+        newmtl blue
+        Kd 0 0 1
+        d 0.5
+    ";
+
+    fn materials(library: &str) -> Result<Vec<Material>, String> {
+        assert_eq!(library, "scene.mtl");
+        parse_mtl(MTL, |file| if file == "red.png" { Ok(Texture::new(2, 2)) } else { Err(format!("missing {file}")) })
+    }
+
+    #[test]
+    fn faces_groups_and_materials() {
+        let obj = "
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 1
+            vn 0 0 1
+
+            g quad
+            usemtl red
+            f 1/1/1 2/1/1 3/2/1 4/2/1
+
+            g triangle
+            usemtl blue
+            f -4//1 -3//1 -2//1
+        ";
+
+        let model = parse_obj(obj, materials).unwrap();
+
+        //The quad is split in two, the triangle gets its own vertices for the new material
+        assert_eq!(model.indices, vec![0, 1, 2, 0, 2, 3, 4, 5, 6]);
+        assert_eq!(model.vertices.positions.len(), 7);
+        assert_eq!(model.vertices.uvs[2], Vec2::ONE);
+        assert_eq!(model.vertices.normals[5], Vec3::Z);
+        assert_eq!(model.vertices.colours[0], Vec3::X);
+        assert_eq!(model.vertices.colours[4], Vec3::Z);
+
+        assert_eq!(model.groups.len(), 2);
+        assert_eq!((model.groups[0].name.as_str(), model.groups[0].material, model.groups[0].indices.clone()), ("quad", Some(0), 0..6));
+        assert_eq!((model.groups[1].name.as_str(), model.groups[1].material, model.groups[1].indices.clone()), ("triangle", Some(1), 6..9));
+
+        assert!(model.materials[0].diffuse_texture.is_some());
+        assert_eq!(model.materials[1].dissolve, 0.5);
+    }
+
+    #[test]
+    fn uvs_take_one_to_three_values() {
+        let obj = "v 0 0 0\nvt 0.5\nvt 0.25 0.75 0\nf 1/1 1/2 1/1\n";
+        let model = parse_obj(obj, |_| Ok(Vec::new())).unwrap();
+
+        assert_eq!(model.vertices.uvs, vec![Vec2::new(0.5, 0.0), Vec2::new(0.25, 0.75)]);
+
+        let too_many = parse_obj("vt 0 0 0 0\n", |_| Ok(Vec::new()));
+        assert_eq!(too_many.err().unwrap(), "line 1: expected 1 to 3 values, found 4");
+    }
+
+    #[test]
+    fn malformed_files_report_line_numbers() {
+        let no_materials = |_: &str| Ok(Vec::new());
+
+        let bad_number = parse_obj("v 0 0 0\nv 1 x 0\n", no_materials);
+        assert_eq!(bad_number.err().unwrap(), "line 2: invalid number 'x'");
+
+        let out_of_range = parse_obj("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n", no_materials);
+        assert_eq!(out_of_range.err().unwrap(), "line 4: index 3 out of range");
+
+        let unknown = parse_obj("# comment\nusemtl stone\n", no_materials);
+        assert_eq!(unknown.err().unwrap(), "line 2: unknown material 'stone'");
+
+        let missing_texture = parse_mtl("newmtl a\nmap_Kd a.png\n", |file| Err(format!("missing {file}")));
+        assert_eq!(missing_texture.err().unwrap(), "line 2: missing a.png");
+    }
+}
@@ -0,0 +1,84 @@
+use crate::camera::Camera;
+use crate::math::bounding_volume::Containment;
+use crate::math::plane::Plane;
+use super::data::MeshBounds;
+
+//Counts of the draws tested since the last reset
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CullStats {
+    pub inside: usize,
+    pub intersecting: usize,
+    pub culled: usize
+}
+
+impl CullStats {
+    pub fn tested(&self) -> usize {
+        self.inside + self.intersecting + self.culled
+    }
+}
+
+//Tests draws against the frustum of a camera before they reach VertexShader::dispatch
+#[derive(Debug, Default, Clone)]
+pub struct FrustumCuller {
+    planes: Vec<Plane>,
+    pub stats: CullStats
+}
+
+impl FrustumCuller {
+
+    pub fn new(camera: &Camera) -> Self {
+        Self { planes: camera.generate_frustum(), stats: CullStats::default() }
+    }
+
+    //Takes the frustum of the camera for the current frame and clears the counts
+    pub fn update(&mut self, camera: &Camera) {
+        self.planes = camera.generate_frustum();
+        self.stats = CullStats::default();
+    }
+
+    pub fn classify(&mut self, bounds: &MeshBounds, model: &glam::Mat4) -> Containment {
+        let result = bounds.classify(model, &self.planes);
+
+        match result {
+            Containment::Inside => self.stats.inside += 1,
+            Containment::Intersect => self.stats.intersecting += 1,
+            Containment::Outside => self.stats.culled += 1
+        }
+
+        result
+    }
+
+    //True if the draw can be skipped
+    pub fn cull(&mut self, bounds: &MeshBounds, model: &glam::Mat4) -> bool {
+        self.classify(bounds, model) == Containment::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+    use crate::camera::{Camera, Projection};
+    use crate::math::bounding_volume::Containment;
+    use crate::renderer::data::MeshBounds;
+    use super::{CullStats, FrustumCuller};
+
+    #[test]
+    fn draws_are_classified_and_counted() {
+        let camera = Camera {
+            projection: Projection::Perspective { fov: std::f32::consts::FRAC_PI_2 }, aspect_ratio: 1.0, near: 0.1, far: 100.0, ..Default::default()
+        };
+        let bounds = MeshBounds::from_points(&[Vec3::splat(-0.5), Vec3::splat(0.5)]);
+
+        let mut culler = FrustumCuller::new(&camera);
+        assert_eq!(culler.classify(&bounds, &Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0))), Containment::Inside);
+        assert_eq!(culler.classify(&bounds, &Mat4::from_translation(Vec3::new(5.0, 0.0, -5.0))), Containment::Intersect);
+        assert!(culler.cull(&bounds, &Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0))));
+        assert!(culler.cull(&bounds, &Mat4::from_translation(Vec3::new(0.0, 0.0, -200.0))));
+
+        assert_eq!(culler.stats, CullStats { inside: 1, intersecting: 1, culled: 2 });
+        assert_eq!(culler.stats.tested(), 4);
+
+        culler.update(&camera);
+        assert_eq!(culler.stats.tested(), 0);
+    }
+}
@@ -2,26 +2,73 @@ use glam::Vec2;
 use glam::Vec3;
 use glam::Vec4;
 
+use crate::math::bounding_volume::Aabb;
+use crate::math::bounding_volume::BoundingSphere;
+use crate::math::bounding_volume::Containment;
+use crate::math::plane::Plane;
+use super::primitive::PrimitiveType;
+
 //Input for vertex shader
 #[derive(Default)]
 pub struct VertexInput {
     pub positions: Vec<Vec3>,
     pub colours: Vec<Vec3>,
     pub uvs: Vec<Vec2>,
+    pub normals: Vec<Vec3>,
+}
+
+//Object space bounds of a mesh
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MeshBounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere
 }
 
-impl VertexInput {
-    pub fn retrieve<T>(source: &[T], indices: [usize; 3], ) -> [T; 3] 
-    where T: Clone
-    {
-        [source[indices[0]].clone(), source[indices[1]].clone(), source[indices[2]].clone()]
+impl MeshBounds {
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+        Self {
+            aabb: Aabb::from_points(points).unwrap_or_default(),
+            sphere: BoundingSphere::from_points(points).unwrap_or_default()
+        }
+    }
+
+    //Tests the bounds placed with model against world space planes, the sphere rejects or accepts cheaply
+    //and the box settles whatever it leaves undecided
+    pub fn classify(&self, model: &glam::Mat4, planes: &[Plane]) -> Containment {
+        match self.sphere.transform(model).classify(planes) {
+            Containment::Intersect => self.aabb.transform(model).classify(planes),
+            result => result
+        }
     }
 }
 
-//Output for vertex shader
+//Vertices and triangle list indices of a mesh together with its bounds
 #[derive(Default)]
-pub struct VertexOutput {
+pub struct Mesh {
+    pub vertices: VertexInput,
+    pub indices: Vec<usize>,
+    pub bounds: MeshBounds
+}
+
+impl Mesh {
+    pub fn new(vertices: VertexInput, indices: Vec<usize>) -> Self {
+        let bounds = MeshBounds::from_points(&vertices.positions);
+        Self { vertices, indices, bounds }
+    }
+}
+
+//Output for vertex shader, the index buffer that goes with it lists primitives of this type
+pub struct VertexOutput<V> {
+    pub primitive: PrimitiveType,
     pub ndc_positions: Vec<Vec4>,
-    pub colours: Vec<Vec3>,
-    pub uvs: Vec<Vec2>
+    //Depth of every vertex, z / w divided in f64 so F64 depth buffers are not limited by the f32 ndc position
+    pub depths: Vec<f64>,
+    pub varyings: Vec<V>
+}
+
+impl<V> Default for VertexOutput<V> {
+    fn default() -> Self {
+        Self { primitive: PrimitiveType::default(), ndc_positions: Vec::new(), depths: Vec::new(), varyings: Vec::new() }
+    }
 }
@@ -1,8 +1,47 @@
 use crate::camera::Camera;
+use crate::texture::FrameBuffer;
 use crate::texture::Texture;
-use crate::math::bounding_box::BoundingBox;
-use crate::math::bounding_box::Line;
+use crate::math;
 use crate::math::colour;
+use super::fragment::FragmentShader;
+use super::primitive::PrimitiveTopology;
+use super::programs::{ColourFragmentProgram, ColourVertexInput, ColourVertexProgram};
+use super::state::{BlendState, RenderState};
+use super::vertex::VertexShader;
+
+//On and off lengths in pixels, measured from the start of the line
+#[derive(Debug, Clone, Copy)]
+pub struct DashPattern {
+    pub on: f32,
+    pub off: f32
+}
+
+impl DashPattern {
+    //How much of a pixel at this distance along the line lies within a dash
+    fn coverage(&self, along: f32) -> f32 {
+        let period = self.on + self.off;
+        if period <= 0.0 { return 1.0; }
+
+        //Both ends of a dash fade over half a pixel, including the start of the next one wrapping into this pixel
+        let phase = along.rem_euclid(period);
+        let current = (phase + 0.5).min(self.on - phase + 0.5).clamp(0.0, 1.0);
+        current.max((phase - period + 0.5).clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineStyle {
+    pub colour: glam::Vec4,
+    //In pixels
+    pub thickness: f32,
+    pub dash: Option<DashPattern>
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self { colour: glam::Vec4::new(1.0, 0.0, 1.0, 1.0), thickness: 1.0, dash: None }
+    }
+}
 
 #[derive(Default)]
 pub struct DebugLineShader {
@@ -10,7 +49,42 @@ pub struct DebugLineShader {
 }
 
 impl DebugLineShader {
-    pub fn dispatch(&self, out: &mut Texture, line_list: &[(glam::Vec3, glam::Vec3)]) {
+
+    //Depth tested lines through the main pipeline, each line is (start, end, colour)
+    pub fn draw_lines(&self, state: &RenderState, target: &mut FrameBuffer, line_list: &[(glam::Vec3, glam::Vec3, glam::Vec4)]) {
+        let mut input = ColourVertexInput::default();
+
+        for (start, end, colour) in line_list {
+            input.positions.extend([*start, *end]);
+            input.colours.extend([*colour, *colour]);
+        }
+
+        self.draw(state, target, PrimitiveTopology::LineList, &input);
+    }
+
+    //Depth tested points through the main pipeline, each point is (position, colour)
+    pub fn draw_points(&self, state: &RenderState, target: &mut FrameBuffer, point_list: &[(glam::Vec3, glam::Vec4)]) {
+        let input = ColourVertexInput {
+            positions: point_list.iter().map(|(position, _)| *position).collect(),
+            colours: point_list.iter().map(|(_, colour)| *colour).collect()
+        };
+
+        self.draw(state, target, PrimitiveTopology::PointList, &input);
+    }
+
+    fn draw(&self, state: &RenderState, target: &mut FrameBuffer, topology: PrimitiveTopology, input: &ColourVertexInput) {
+        let mut vs = VertexShader::<ColourVertexProgram>::default();
+        (vs.uniforms.view, vs.uniforms.projection) = self.camera.generate_view_projection();
+        vs.clip.set_camera(&self.camera);
+
+        let indices: Vec<usize> = (0..input.positions.len()).collect();
+        let (vs_output, primitive_indices) = vs.dispatch(state, topology, input, &indices);
+
+        FragmentShader::<ColourFragmentProgram>::default().dispatch(state, target, &vs_output, &primitive_indices);
+    }
+
+    //Overlay without depth testing, blended straight onto the texture
+    pub fn dispatch(&self, out: &mut Texture, line_list: &[(glam::Vec3, glam::Vec3, LineStyle)]) {
 
         let (view, projection) = self.camera.generate_view_projection();
         let vp = projection * view;
@@ -24,72 +98,105 @@ impl DebugLineShader {
             glam::Vec2::new(half_screen_width, half_screen_height)
         );
 
-        for (start, end) in line_list {
-           
-            //calculate ndc coordinates of triangle
-            let proj_1 = vp * start.extend(1.0);
-            let proj_2 = vp * end.extend(1.0);
+        for (start, end, style) in line_list {
 
-            if proj_1.z > proj_1.w || proj_1.z < 0.0 || proj_2.z > proj_2.w || proj_2.z < 0.0 {
+            //Clipped in homogenous space so lines crossing the near plane are kept
+            let Some([(proj_1, _), (proj_2, _)]) = math::clip_homogenous_line((vp * start.extend(1.0), 0.0), (vp * end.extend(1.0), 0.0), &math::CLIP_PLANES) else {
                 continue;
-            }
-
-            let inv_w1 = 1.0 / proj_1.w;
-            let inv_w2 = 1.0 / proj_2.w;
+            };
 
             //Homogenous divide
-            let ndc_1 = proj_1.truncate() * inv_w1;
-            let ndc_2 = proj_2.truncate() * inv_w2;
+            let ndc_1 = proj_1.truncate() / proj_1.w;
+            let ndc_2 = proj_2.truncate() / proj_2.w;
+
+            let screen_start = screen_space_matrix.mul_vec3(ndc_1.truncate().extend(1.0)).truncate();
+            let screen_end = screen_space_matrix.mul_vec3(ndc_2.truncate().extend(1.0)).truncate();
 
-            self.draw_line(out, &screen_space_matrix, ndc_1, ndc_2);
+            self.draw_line(out, screen_start, screen_end, style);
         }
     }
 
-    fn draw_line(&self, out: &mut Texture, screen_space_matrix: &glam::Mat3, start: glam::Vec3, end: glam::Vec3) {
+    //Coverage based, every pixel is blended by how much of it lies within the line and its current dash
+    pub fn draw_line(&self, out: &mut Texture, start: glam::Vec2, end: glam::Vec2, style: &LineStyle) {
 
-        let screen_bounds = BoundingBox::new(
-            glam::UVec2::new(0, 0), 
-            glam::UVec2::new(out.width() as u32 - 1, out.height() as u32 - 1)
-        );
+        if out.width() == 0 || out.height() == 0 { return; }
 
-        let screen_start = screen_space_matrix.mul_vec3(start).truncate();
-        let screen_end = screen_space_matrix.mul_vec3(end).truncate();
+        //Half a pixel of falloff on each side of the line
+        let reach = style.thickness.max(0.0) * 0.5 + 0.5;
 
-        if let Some(clipped_line) = screen_bounds.clip_line(&Line::new(screen_start, screen_end)) {
-          
-            let clipped_start = clipped_line.start.as_uvec2();
-            let clipped_end = clipped_line.end.as_uvec2();
+        let min = (start.min(end) - reach).floor().max(glam::Vec2::ZERO);
+        let max = (start.max(end) + reach).ceil().min(glam::Vec2::new(out.width() as f32, out.height() as f32));
 
-            let mut x = clipped_start.x as i32;
-            let mut y = clipped_start.y as i32;
+        let direction = end - start;
+        let length = direction.length();
 
-            let final_x = clipped_end.x as i32;
-            let final_y = clipped_end.y as i32;
+        for j in (min.y as usize)..(max.y as usize) {
+            for i in (min.x as usize)..(max.x as usize) {
 
-            let dx = (final_x - x).abs();
-            let dy = -(final_y - y).abs();
+                let point = glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
 
-            let sx = if x < final_x { 1 } else { -1 }; 
-            let sy = if y < final_y { 1 } else { -1 }; 
+                //Distance along and away from the segment, end points are rounded
+                let along = if length > 0.0 { (point - start).dot(direction / length).clamp(0.0, length) } else { 0.0 };
+                let distance = point.distance(start + direction * (along / length.max(f32::EPSILON)));
 
-            let mut error = dy + dx;
+                let mut coverage = (reach - distance).clamp(0.0, 1.0);
 
-            loop {
-                out.write(x as usize, y as usize, colour::f32_to_hex(1.0, 0.0, 1.0, 0.0));
+                if let Some(dash) = &style.dash {
+                    coverage *= dash.coverage(along);
+                }
 
-                if x == final_x && y == final_y { break };
-                let e2 = 2 * error;
+                if coverage <= 0.0 { continue; }
 
-                if e2 >= dy {
-                    error = error + dy;
-                    x = x + sx;
-                }
+                let colour = style.colour * glam::Vec4::new(1.0, 1.0, 1.0, coverage);
+                let stored = colour::hex_to_f32(out.read(i, j));
 
-                if e2 <= dx {
-                    error = error + dx;
-                    y = y + sy;
-                }
+                out.write(i, j, colour::vec4_to_hex(BlendState::ALPHA.blend(colour, stored)));
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec4};
+    use crate::texture::Texture;
+    use super::{DashPattern, DebugLineShader, LineStyle};
+
+    fn draw(style: LineStyle) -> Texture {
+        let mut out = Texture::new(16, 16);
+        out.clear(0xFF000000);
+
+        DebugLineShader::default().draw_line(&mut out, Vec2::new(0.0, 8.0), Vec2::new(16.0, 8.0), &style);
+        out
+    }
+
+    fn red(out: &Texture, i: usize, j: usize) -> u32 { (out.read(i, j) >> 16) & 0xFF }
+
+    #[test]
+    fn thickness_and_antialiasing() {
+        let out = draw(LineStyle { colour: Vec4::new(1.0, 0.0, 0.0, 1.0), thickness: 3.0, dash: None });
+
+        //Rows 7 and 8 are fully inside, the falloff reaches halfway into rows 6 and 9
+        assert_eq!(red(&out, 4, 7), 0xFF);
+        assert_eq!(red(&out, 4, 8), 0xFF);
+        assert!(red(&out, 4, 6) > 0 && red(&out, 4, 6) < 0xFF);
+        assert_eq!(red(&out, 4, 5), 0);
+    }
+
+    #[test]
+    fn dashes_leave_gaps() {
+        let dash = DashPattern { on: 4.0, off: 4.0 };
+        let out = draw(LineStyle { colour: Vec4::new(1.0, 0.0, 0.0, 1.0), thickness: 2.0, dash: Some(dash) });
+
+        assert_eq!(red(&out, 1, 7), 0xFF);
+        assert_eq!(red(&out, 5, 7), 0);
+        assert_eq!(red(&out, 9, 7), 0xFF);
+
+        //Ends of a dash are anti-aliased on both sides
+        let close = |along: f32, expected: f32| assert!((dash.coverage(along) - expected).abs() < 1e-5, "coverage at {along}");
+        close(0.0, 0.5);
+        close(3.8, 0.7);
+        close(6.0, 0.0);
+        close(7.8, 0.3);
+    }
+}
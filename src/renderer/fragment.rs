@@ -1,92 +1,806 @@
-use crate::texture::Sampler;
-use crate::texture::Texture;
-use crate::texture::DepthTexture;
+use std::ops::Range;
+
+use crate::texture::FrameBuffer;
+use crate::texture::FrameBufferBand;
 use crate::math;
+use crate::math::varying::Varying;
+use crate::math::varying::Weights;
 use super::data::VertexOutput;
+use super::primitive::PrimitiveType;
+use super::raster;
+use super::raster::TriangleSetup;
+use super::state::{FillMode, RenderState};
+
+//Per pixel input handed to the fragment program
+pub struct Fragment<V> {
+    //Window space x and y, ndc depth and 1/w
+    pub position: glam::Vec4,
+    pub front_facing: bool,
+    pub varyings: V
+}
+
+//User-defined fragment stage, invoked once per pixel with at least one sample passing the stencil and depth tests
+pub trait FragmentProgram {
+    type Uniforms;
+    type Varyings: Varying;
+
+    //Returns the RGBA colour of the fragment
+    fn shade(&self, uniforms: &Self::Uniforms, fragment: &Fragment<Self::Varyings>) -> glam::Vec4;
+}
+
+pub struct FragmentShader<P: FragmentProgram> {
+    pub program: P,
+    pub uniforms: P::Uniforms
+}
+
+impl<P> Default for FragmentShader<P>
+where P: FragmentProgram + Default, P::Uniforms: Default
+{
+    fn default() -> Self {
+        Self { program: P::default(), uniforms: P::Uniforms::default() }
+    }
+}
+
+//Tiles are square, a row of tiles is the unit of work handed to a thread
+pub const TILE_SIZE: usize = 32;
+
+enum Shape {
+    Triangle,
+    //End points in screen space, fragments are placed by projecting onto the segment
+    Line { start: glam::Vec2, end: glam::Vec2 },
+    Point
+}
+
+//Primitive after setup, shared by every tile it touches
+struct PreparedPrimitive {
+    shape: Shape,
+    //Triangles only use the first one, lines and points are covered by two halves of a quad
+    coverage: [Option<TriangleSetup>; 2],
+    //Lines repeat their end vertex and points their only vertex
+    indices: [usize; 3],
+    front_facing: bool,
+    //Depth bias of the triangle the primitive comes from
+    depth_offset: f32,
+    x_range: Range<usize>,
+    y_range: Range<usize>
+}
+
+impl PreparedPrimitive {
+
+    fn new(shape: Shape, coverage: [Option<TriangleSetup>; 2], indices: [usize; 3], front_facing: bool, width: usize, height: usize) -> Option<Self> {
+
+        let (x_range, y_range) = coverage.iter().flatten()
+            .filter_map(|setup| setup.pixel_bounds(width, height))
+            .reduce(|(x_a, y_a), (x_b, y_b)| (x_a.start.min(x_b.start)..x_a.end.max(x_b.end), y_a.start.min(y_b.start)..y_a.end.max(y_b.end)))?;
+
+        Some(Self { shape, coverage, indices, front_facing, depth_offset: 0.0, x_range, y_range })
+    }
+
+    //Screen space weights of the three vertices at a sample, edge_values are the ones of the triangle that covered it
+    fn sample_weights(&self, x: f32, y: f32, edge_values: &[i64; 3]) -> glam::Vec3 {
+        match &self.shape {
+            Shape::Triangle => self.coverage[0].map_or(glam::Vec3::X, |setup| setup.barycentric(edge_values)),
+            Shape::Line { start, end } => {
+                let direction = *end - *start;
+                let t = ((glam::Vec2::new(x, y) - *start).dot(direction) / direction.length_squared()).clamp(0.0, 1.0);
+                glam::Vec3::new(1.0 - t, t, 0.0)
+            }
+            Shape::Point => glam::Vec3::X
+        }
+    }
+
+    //Distance in pixels from the pixel centre to the closest triangle edge, None for lines and points
+    fn centre_edge_distance(&self, i: usize, j: usize) -> Option<f32> {
+        let setup = match (&self.shape, &self.coverage[0]) {
+            (Shape::Triangle, Some(setup)) => setup,
+            _ => return None
+        };
 
+        let distances = setup.edge_distances(&setup.evaluate(raster::sample_to_fixed(i, 0.5), raster::sample_to_fixed(j, 0.5)));
+        Some(distances.min_element())
+    }
+
+    //Point the fragment program runs at and its weights, the pixel centre unless the triangle misses it
+    //In that case the first covered sample is used so the varyings are never extrapolated past the edges
+    fn centroid(&self, i: usize, j: usize, inside: u8, edge_values: &[[i64; 3]], sample_positions: &[(f32, f32)]) -> (glam::Vec2, glam::Vec3) {
+        let centre = glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
+
+        let setup = match (&self.shape, &self.coverage[0]) {
+            (Shape::Triangle, Some(setup)) => setup,
+            _ => return (centre, self.sample_weights(centre.x, centre.y, &[0; 3]))
+        };
+
+        let centre_values = setup.evaluate(raster::sample_to_fixed(i, 0.5), raster::sample_to_fixed(j, 0.5));
+        if setup.is_inside(&centre_values) { return (centre, setup.barycentric(&centre_values)); }
 
-#[derive(Default)]
-pub struct FragmentShader {
-    pub mesh_texture: Texture,
-    pub mesh_sampler: Sampler
+        match (0..sample_positions.len()).find(|sample| inside & (1 << sample) != 0) {
+            Some(sample) => {
+                let (x, y) = sample_positions[sample];
+                (glam::Vec2::new(i as f32 + x, j as f32 + y), setup.barycentric(&edge_values[sample]))
+            }
+            None => (centre, setup.barycentric(&centre_values))
+        }
+    }
 }
 
-impl FragmentShader {
-    pub fn dispatch(&self, out: &mut Texture, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: &[usize]) {
+//Two triangles covering the quad, sharing the c1 - c3 diagonal
+fn quad_coverage(c1: glam::Vec2, c2: glam::Vec2, c3: glam::Vec2, c4: glam::Vec2) -> [Option<TriangleSetup>; 2] {
+    [TriangleSetup::new(c1, c2, c3), TriangleSetup::new(c3, c4, c1)]
+}
+
+fn intersect_ranges(a: &Range<usize>, b: &Range<usize>) -> Range<usize> {
+    a.start.max(b.start)..a.end.min(b.end)
+}
 
-        debug_assert!(out.width() == depth_buffer.width());
-        debug_assert!(out.height() == depth_buffer.height());
+impl<P: FragmentProgram> FragmentShader<P> {
 
-        let half_screen_width = (out.width() as f32) * 0.5;
-        let half_screen_height = (out.height() as f32) * 0.5;
+    pub fn new(program: P, uniforms: P::Uniforms) -> Self {
+        Self { program, uniforms }
+    }
+
+    fn screen_space_matrix(width: usize, height: usize) -> glam::Mat3 {
+        let half_screen_width = (width as f32) * 0.5;
+        let half_screen_height = (height as f32) * 0.5;
 
-        let screen_space_matrix = glam::Mat3::from_scale_angle_translation(
+        glam::Mat3::from_scale_angle_translation(
             glam::Vec2::new(half_screen_width, -half_screen_height),
             0.0,
             glam::Vec2::new(half_screen_width, half_screen_height)
+        )
+    }
+
+    fn prepare_all<'a>(state: &'a RenderState, vs_output: &'a VertexOutput<P::Varyings>, indices: &'a [usize], width: usize, height: usize) -> impl Iterator<Item = PreparedPrimitive> + 'a {
+        let screen_space_matrix = Self::screen_space_matrix(width, height);
+        let primitive = vs_output.primitive;
+
+        indices.chunks_exact(primitive.vertex_count()).flat_map(move |primitive_indices| {
+            Self::setup_primitive(state, vs_output, primitive, primitive_indices, &screen_space_matrix, width, height)
+        }).flatten()
+    }
+
+    pub fn dispatch(&self, state: &RenderState, target: &mut FrameBuffer, vs_output: &VertexOutput<P::Varyings>, indices: &[usize]) {
+
+        let (width, height) = (target.width(), target.height());
+        let mut band = target.as_band();
+
+        for prepared in Self::prepare_all(state, vs_output, indices, width, height) {
+            self.rasterize_region(state, &mut band, vs_output, &prepared, prepared.x_range.clone(), prepared.y_range.clone());
+        }
+    }
+
+    //Same output as dispatch, primitives are set up once, binned into tiles and every row of tiles is rasterized on one of `workers` threads
+    //Each pixel still sees the primitives in submission order, so the result does not depend on scheduling
+    pub fn dispatch_parallel(&self, state: &RenderState, target: &mut FrameBuffer, vs_output: &VertexOutput<P::Varyings>, indices: &[usize], workers: usize)
+    where P: Sync, P::Uniforms: Sync, P::Varyings: Sync
+    {
+        let (width, height) = (target.width(), target.height());
+
+        //Setup
+        let prepared: Vec<PreparedPrimitive> = Self::prepare_all(state, vs_output, indices, width, height).collect();
+
+        //Binning, in submission order
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); tiles_x * tiles_y];
+
+        for (id, primitive) in prepared.iter().enumerate() {
+            for tile_y in (primitive.y_range.start / TILE_SIZE)..primitive.y_range.end.div_ceil(TILE_SIZE) {
+                for tile_x in (primitive.x_range.start / TILE_SIZE)..primitive.x_range.end.div_ceil(TILE_SIZE) {
+                    bins[tile_y * tiles_x + tile_x].push(id);
+                }
+            }
+        }
+
+        //Rows of tiles are handed out round robin
+        let workers = workers.max(1);
+        let mut work: Vec<Vec<FrameBufferBand>> = (0..workers).map(|_| Vec::new()).collect();
+        for (row, band) in target.bands_mut(TILE_SIZE).into_iter().enumerate() {
+            work[row % workers].push(band);
+        }
+
+        let (prepared, bins) = (&prepared, &bins);
+
+        std::thread::scope(|scope| {
+            for mut bands in work {
+                scope.spawn(move || {
+                    for band in bands.iter_mut() {
+                        let tile_y = band.start_row() / TILE_SIZE;
+                        let rows = band.rows();
+
+                        for tile_x in 0..tiles_x {
+                            let columns = (tile_x * TILE_SIZE)..((tile_x + 1) * TILE_SIZE).min(width);
+
+                            for id in &bins[tile_y * tiles_x + tile_x] {
+                                let primitive = &prepared[*id];
+                                let x_range = intersect_ranges(&primitive.x_range, &columns);
+                                let y_range = intersect_ranges(&primitive.y_range, &rows);
+
+                                self.rasterize_region(state, band, vs_output, primitive, x_range, y_range);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    //Culling, snapping and bounds, triangles drawn as wireframe or points turn into up to three primitives
+    //Primitives that can not produce any sample are None
+    fn setup_primitive(state: &RenderState, vs_output: &VertexOutput<P::Varyings>, primitive: PrimitiveType, indices: &[usize], screen_matrix: &glam::Mat3, width: usize, height: usize) -> [Option<PreparedPrimitive>; 3] {
+
+        let to_screen = |index: usize| {
+            let ndc = vs_output.ndc_positions[index];
+            screen_matrix.mul_vec3(ndc.truncate().truncate().extend(1.0)).truncate()
+        };
+
+        match primitive {
+            PrimitiveType::Triangle => {
+                let v1 = vs_output.ndc_positions[indices[0]];
+                let v2 = vs_output.ndc_positions[indices[1]];
+                let v3 = vs_output.ndc_positions[indices[2]];
+
+                //Facing is decided on the projected triangle, before any pixel is touched
+                let clockwise = math::cull_back_face(v1.truncate(), v2.truncate(), v3.truncate());
+                let front_facing = state.front_face.is_front(clockwise);
+                if state.is_culled(front_facing) { return [None, None, None]; }
+
+                let corners = [indices[0], indices[1], indices[2]];
+                let screen = corners.map(to_screen);
+
+                let mut prepared = match state.fill_mode {
+                    FillMode::Wireframe => [0, 1, 2].map(|k| {
+                        let (start, end) = (corners[k], corners[(k + 1) % 3]);
+                        Self::setup_line(state, screen[k], screen[(k + 1) % 3], [start, end, end], front_facing, width, height)
+                    }),
+                    FillMode::Points => [0, 1, 2].map(|k| {
+                        Self::setup_point(state, screen[k], corners[k], front_facing, width, height)
+                    }),
+                    FillMode::Solid | FillMode::SolidWireframe { .. } => {
+                        //Snaps to fixed point, degenerate triangles have nothing to draw
+                        let setup = TriangleSetup::new(screen[0], screen[1], screen[2]);
+                        let prepared = setup.and_then(|setup| PreparedPrimitive::new(Shape::Triangle, [Some(setup), None], corners, front_facing, width, height));
+                        [prepared, None, None]
+                    }
+                };
+
+                if !state.depth_bias.is_none() {
+                    let depth_offset = Self::depth_offset(state, screen, glam::Vec3::new(v1.z, v2.z, v3.z));
+                    prepared.iter_mut().flatten().for_each(|primitive| primitive.depth_offset = depth_offset);
+                }
+
+                prepared
+            }
+            PrimitiveType::Line => {
+                [Self::setup_line(state, to_screen(indices[0]), to_screen(indices[1]), [indices[0], indices[1], indices[1]], true, width, height), None, None]
+            }
+            PrimitiveType::Point => {
+                [Self::setup_point(state, to_screen(indices[0]), indices[0], true, width, height), None, None]
+            }
+        }
+    }
+
+    //Bias from the steepest depth slope of the triangle in screen space
+    fn depth_offset(state: &RenderState, screen: [glam::Vec2; 3], depths: glam::Vec3) -> f32 {
+        let (edge_1, edge_2) = (screen[1] - screen[0], screen[2] - screen[0]);
+        let (depth_1, depth_2) = (depths.y - depths.x, depths.z - depths.x);
+
+        let area = edge_1.perp_dot(edge_2);
+        let max_slope = if area == 0.0 { 0.0 } else {
+            let slope_x = (depth_1 * edge_2.y - depth_2 * edge_1.y) / area;
+            let slope_y = (depth_2 * edge_1.x - depth_1 * edge_2.x) / area;
+            slope_x.abs().max(slope_y.abs())
+        };
+
+        state.depth_bias.offset(depths.abs().max_element(), max_slope)
+    }
+
+    //Quad of line_width around the segment
+    fn setup_line(state: &RenderState, start: glam::Vec2, end: glam::Vec2, indices: [usize; 3], front_facing: bool, width: usize, height: usize) -> Option<PreparedPrimitive> {
+        let direction = (end - start).try_normalize()?;
+        let offset = direction.perp() * state.line_width * 0.5;
+
+        let coverage = quad_coverage(start + offset, start - offset, end - offset, end + offset);
+        PreparedPrimitive::new(Shape::Line { start, end }, coverage, indices, front_facing, width, height)
+    }
+
+    //Square of point_size around the centre
+    fn setup_point(state: &RenderState, centre: glam::Vec2, index: usize, front_facing: bool, width: usize, height: usize) -> Option<PreparedPrimitive> {
+        let half = state.point_size * 0.5;
+
+        let coverage = quad_coverage(
+            centre + glam::Vec2::new(-half, -half), centre + glam::Vec2::new(-half, half),
+            centre + glam::Vec2::new(half, half), centre + glam::Vec2::new(half, -half)
         );
+        PreparedPrimitive::new(Shape::Point, coverage, [index; 3], front_facing, width, height)
+    }
+
+    //Stencil then depth test, applying the stencil operations and depth write, returns true if the sample survives
+    fn depth_stencil_test(state: &RenderState, target: &mut FrameBufferBand, i: usize, j: usize, sample: usize, depth: f64, front_facing: bool) -> bool {
+
+        let depth_pass = state.depth_function.compare(depth, target.read_depth(i, j, sample));
 
-        let triangle_count = indices.len() / 3;
+        if let Some(stencil) = &state.stencil {
+            let face = stencil.face(front_facing);
+            let stored = target.read_stencil(i, j, sample);
 
-        for i in 0..triangle_count {
-            let triangle_indices = [indices[i * 3], indices[i * 3 + 1], indices[i * 3 + 2]];
-            self.rasterize_triangle(out, depth_buffer, vs_output, triangle_indices, &screen_space_matrix);
+            let (passed, operation) = if !stencil.test(face, stored) {
+                (false, face.fail)
+            } else if !depth_pass {
+                (false, face.depth_fail)
+            } else {
+                (true, face.pass)
+            };
+
+            target.write_stencil(i, j, sample, stencil.update(operation, stored));
+            if !passed { return false; }
+        }
+        else if !depth_pass {
+            return false;
         }
+
+        if state.depth_write { target.write_depth(i, j, sample, depth); }
+        true
     }
 
-    fn rasterize_triangle(&self, out: &mut Texture, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: [usize; 3], screen_matrix: &glam::Mat3) -> Option<()> {
-        
+    fn rasterize_region(&self, state: &RenderState, target: &mut FrameBufferBand, vs_output: &VertexOutput<P::Varyings>, primitive: &PreparedPrimitive, x_range: Range<usize>, y_range: Range<usize>) {
+
+        let indices = primitive.indices;
+
         let v1 = vs_output.ndc_positions[indices[0]];
         let v2 = vs_output.ndc_positions[indices[1]];
         let v3 = vs_output.ndc_positions[indices[2]];
 
-        let colour1 = vs_output.colours[indices[0]];
-        let colour2 = vs_output.colours[indices[1]];
-        let colour3 = vs_output.colours[indices[2]];
+        let varyings1 = vs_output.varyings[indices[0]];
+        let varyings2 = vs_output.varyings[indices[1]];
+        let varyings3 = vs_output.varyings[indices[2]];
 
-        let uv1 = vs_output.uvs[indices[0]];
-        let uv2 = vs_output.uvs[indices[1]];
-        let uv3 = vs_output.uvs[indices[2]];
+        let sample_positions = target.samples().positions();
+        let depths = indices.map(|index| vs_output.depths[index]);
+        let inv_w = glam::Vec3::new(v1.w, v2.w, v3.w);
 
-        let screen_1 = screen_matrix.mul_vec3(v1.truncate().truncate().extend(1.0));
-        let screen_2 = screen_matrix.mul_vec3(v2.truncate().truncate().extend(1.0));
-        let screen_3 = screen_matrix.mul_vec3(v3.truncate().truncate().extend(1.0));
+        //Biased depth for a set of screen space weights, interpolated in f64 for F64 depth buffers
+        //Taken relative to the first vertex, so rounding of the weights only scales the depth differences
+        //Always clamped to [0, 1], rounding and bias can push it out even when nothing is past the depth planes
+        let depth_at = |weights: glam::Vec3| {
+            let depth = depths[0] + weights.y as f64 * (depths[1] - depths[0]) + weights.z as f64 * (depths[2] - depths[0]);
+            (depth + primitive.depth_offset as f64).clamp(0.0, 1.0)
+        };
 
-        let triangle_bounds = math::generate_triangle_bounding_box(screen_1.truncate(), screen_2.truncate(), screen_3.truncate());
+        let mut sample_offsets = [(0, 0); raster::MAX_SAMPLES];
+        for (offset, (x, y)) in sample_offsets.iter_mut().zip(sample_positions) {
+            *offset = (raster::to_fixed(*x), raster::to_fixed(*y));
+        }
 
-        let screen_bounds = math::bounding_box::BoundingBox::new(
-            glam::UVec2::new(0, 0), 
-            glam::UVec2::new(out.width() as u32, out.height() as u32)
-        );
+        let sample_offsets = &sample_offsets[..sample_positions.len()];
 
-        let triangle_bounds = triangle_bounds.intersect(&screen_bounds)?;
+        let mut shade_pixel = |i: usize, j: usize, inside: u8, edge_values: &[[i64; 3]; raster::MAX_SAMPLES]| {
 
-        let x_range = (triangle_bounds.start.x as usize)..(triangle_bounds.end.x as usize);
-        let y_range = (triangle_bounds.start.y as usize)..(triangle_bounds.end.y as usize);
+            //Stencil and depth are resolved per sample
+            let mut coverage = 0u8;
+            for (sample, values) in edge_values.iter().enumerate().take(sample_positions.len()) {
+                if inside & (1 << sample) == 0 { continue; }
 
-        for j in y_range {
-            for i in x_range.clone() {
-                
-                let pixel_point = glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
+                let (x, y) = sample_positions[sample];
+                let depth = depth_at(primitive.sample_weights(i as f32 + x, j as f32 + y, values));
 
-                //means the point is inside the triangle
-                if let Some(weights) = math::barycentric_weights(pixel_point, screen_1.truncate(), screen_2.truncate(), screen_3.truncate()) {
-                    let depth = weights.dot(glam::Vec3::new(v1.z, v2.z, v3.z));
+                if Self::depth_stencil_test(state, target, i, j, sample, depth, primitive.front_facing) {
+                    coverage |= 1 << sample;
+                }
+            }
 
-                    if depth_buffer.depth_test(i, j, depth) {
-                        
-                        let depth_correction = 1.0 / (weights.x * v1.w + weights.y * v2.w + weights.z * v3.w);
-                        let colour = math::barycentric_lerp(weights, colour1, colour2, colour3) * depth_correction;
-                        let uv = math::barycentric_lerp(weights, uv1, uv2, uv3) * depth_correction;
+            if coverage == 0 { return; }
 
-                        let out_frag = colour * self.mesh_sampler.sample(&self.mesh_texture, uv).truncate();
+            //Nothing to write, e.g. a depth pre-pass or a stencil mask
+            if state.colour_mask.is_none() { return; }
 
-                        out.write(i, j, math::colour::vec4_to_hex(out_frag.extend(1.0)));
-                    }
+            //The program runs once per pixel, at its centre or a covered sample when the centre is outside
+            let (pixel_point, weights) = primitive.centroid(i, j, inside, edge_values, sample_positions);
+
+            //w holds 1/w, so the varyings are weighted by it to get perspective correct values
+            let interpolation_weights = Weights::from_screen(weights, inv_w);
+
+            let fragment = Fragment {
+                position: glam::Vec4::new(pixel_point.x, pixel_point.y, depth_at(weights) as f32, weights.dot(inv_w)),
+                front_facing: primitive.front_facing,
+                varyings: P::Varyings::interpolate(varyings1, varyings2, varyings3, &interpolation_weights)
+            };
+
+            let mut out_frag = self.program.shade(&self.uniforms, &fragment);
+
+            //Edges blended over the shaded colour with half a pixel of falloff
+            if let (FillMode::SolidWireframe { colour }, Some(distance)) = (state.fill_mode, primitive.centre_edge_distance(i, j)) {
+                let edge = (state.line_width * 0.5 + 0.5 - distance).clamp(0.0, 1.0);
+                out_frag = math::lerp(out_frag, colour, edge);
+            }
+
+            for sample in (0..sample_positions.len()).filter(|sample| coverage & (1 << sample) != 0) {
+
+                let stored = target.read_colour(i, j, sample);
+
+                let blended = match &state.blend {
+                    Some(blend) => blend.blend(out_frag, math::colour::hex_to_f32(stored)),
+                    None => out_frag
+                };
+
+                let colour = state.colour_mask.apply(math::colour::vec4_to_hex(blended), stored);
+                target.write_colour(i, j, sample, colour);
+            }
+        };
+
+        match &primitive.coverage {
+            [Some(setup), None] | [None, Some(setup)] => setup.for_each_covered(x_range, y_range, sample_offsets, &mut shade_pixel),
+            halves => {
+                //Halves of a quad share the pixels along their diagonal, their samples are merged first
+                //so the program still runs once per pixel, lines and points ignore the edge values
+                let mut covered: Vec<(usize, usize, u8)> = Vec::new();
+
+                for setup in halves.iter().flatten() {
+                    //Each half only covers part of the region
+                    let Some((setup_x, setup_y)) = setup.pixel_bounds(x_range.end, y_range.end) else { continue; };
+                    let setup_x = intersect_ranges(&setup_x, &x_range);
+                    let setup_y = intersect_ranges(&setup_y, &y_range);
+
+                    setup.for_each_covered(setup_x, setup_y, sample_offsets, |i, j, inside, _| covered.push((j, i, inside)));
+                }
+
+                covered.sort_unstable_by_key(|(j, i, _)| (*j, *i));
+                covered.dedup_by(|later, earlier| {
+                    let same = later.0 == earlier.0 && later.1 == earlier.1;
+                    if same { earlier.2 |= later.2; }
+                    same
+                });
+
+                let no_edges = [[0; 3]; raster::MAX_SAMPLES];
+                for (j, i, inside) in covered {
+                    shade_pixel(i, j, inside, &no_edges);
                 }
             }
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3, Vec4};
+    use crate::camera::{Camera, Projection};
+    use crate::texture::{DepthFormat, FrameBuffer, SampleCount, Texture};
+    use crate::renderer::primitive::PrimitiveTopology;
+    use crate::renderer::programs::{ColourFragmentProgram, ColourVertexInput, ColourVertexProgram, TextureUniforms, UnlitFragmentProgram, UnlitVaryings};
+    use crate::renderer::vertex::VertexShader;
+    use crate::renderer::data::VertexOutput;
+    use crate::renderer::primitive::PrimitiveType;
+    use crate::renderer::state::{BlendState, ColourMask, CompareFunction, DepthBias, FillMode, RenderState};
+    use crate::renderer::testing::Lcg;
+    use super::{Fragment, FragmentProgram, FragmentShader};
+
+    struct White;
+
+    impl FragmentProgram for White {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, _uniforms: &(), _fragment: &Fragment<f32>) -> Vec4 { Vec4::ONE }
+    }
+
+    struct Translucent;
+
+    impl FragmentProgram for Translucent {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, _uniforms: &(), fragment: &Fragment<f32>) -> Vec4 {
+            Vec4::new(fragment.varyings, 1.0 - fragment.varyings, 0.5, 0.5)
+        }
+    }
+
+    //Overlapping triangles at pseudo random depths, some of them reaching past the screen
+    fn scatter(count: usize) -> (VertexOutput<f32>, Vec<usize>) {
+        let mut lcg = Lcg::new(7);
+        let mut random = move || lcg.next_f32();
+
+        let mut output = VertexOutput::default();
+        for _ in 0..count * 3 {
+            let position = Vec4::new(random() * 2.4 - 1.2, random() * 2.4 - 1.2, random(), 1.0);
+            output.ndc_positions.push(position);
+            output.depths.push(position.z as f64);
+            output.varyings.push(random());
+        }
+
+        (output, (0..count * 3).collect())
+    }
+
+    //Unlit texturing that fails if a uv is extrapolated past the triangle
+    struct CheckedUnlit;
+
+    impl FragmentProgram for CheckedUnlit {
+        type Uniforms = TextureUniforms;
+        type Varyings = UnlitVaryings;
+
+        fn shade(&self, uniforms: &TextureUniforms, fragment: &Fragment<UnlitVaryings>) -> Vec4 {
+            let uv = fragment.varyings.uv;
+            assert!(uv.cmpge(Vec2::splat(-1e-4)).all() && uv.cmple(Vec2::splat(1.0 + 1e-4)).all(), "uv {uv} outside the triangle");
+            UnlitFragmentProgram.shade(uniforms, fragment)
+        }
+    }
+
+    //Lower left half of the screen, split along the diagonal
+    fn half_screen() -> VertexOutput<f32> {
+        VertexOutput {
+            primitive: PrimitiveType::Triangle,
+            ndc_positions: vec![
+                Vec4::new(-1.0, 1.0, 0.5, 1.0),
+                Vec4::new(-1.0, -1.0, 0.5, 1.0),
+                Vec4::new(1.0, -1.0, 0.5, 1.0)
+            ],
+            depths: vec![0.5; 3],
+            varyings: vec![0.0; 3]
+        }
+    }
+
+    #[test]
+    fn msaa_resolves_partial_coverage() {
+        let fs = FragmentShader::new(White, ());
+        let mut target = FrameBuffer::new_multisampled(8, 8, SampleCount::X4);
+        target.clear(0xFF000000, 1.0, 0);
 
-        Some(())
+        fs.dispatch(&RenderState::default(), &mut target, &half_screen(), &[0, 1, 2]);
+
+        let mut resolved = Texture::new(8, 8);
+        target.resolve(&mut resolved);
+
+        //Fully inside, fully outside and on the diagonal
+        assert_eq!(resolved.read(1, 6), 0xFFFFFFFF);
+        assert_eq!(resolved.read(6, 1), 0xFF000000);
+
+        let edge = resolved.read(3, 3) & 0xFF;
+        assert!(edge > 0 && edge < 0xFF);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn msaa_textures_edge_pixels_inside_the_triangle() {
+        let uniforms = TextureUniforms { texture: Texture::from_data(vec![0xFFFF0000, 0xFF00FF00, 0xFF0000FF, 0xFFFFFFFF], 2, 2), ..Default::default() };
+        let fs = FragmentShader::new(CheckedUnlit, uniforms);
+
+        //Edges at odd angles, so many edge pixels have covered samples but an uncovered centre
+        let uvs = [Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)];
+        let triangle = VertexOutput {
+            primitive: PrimitiveType::Triangle,
+            ndc_positions: vec![
+                Vec4::new(-0.93, 0.87, 0.5, 1.0),
+                Vec4::new(-0.71, -0.95, 0.5, 1.0),
+                Vec4::new(0.89, -0.38, 0.5, 1.0)
+            ],
+            depths: vec![0.5; 3],
+            varyings: uvs.iter().map(|uv| UnlitVaryings { colour: Vec3::ONE, uv: *uv }).collect()
+        };
+
+        let mut target = FrameBuffer::new_multisampled(37, 29, SampleCount::X4);
+        target.clear(0xFF000000, 1.0, 0);
+        fs.dispatch(&RenderState::default(), &mut target, &triangle, &[0, 1, 2]);
+
+        assert!(target.colour.as_slice().iter().any(|colour| *colour != 0xFF000000));
+    }
+
+    #[test]
+    fn parallel_matches_single_threaded() {
+        let fs = FragmentShader::new(Translucent, ());
+        let (output, indices) = scatter(64);
+
+        let state = RenderState { blend: Some(BlendState::ALPHA), ..Default::default() };
+
+        let mut single = FrameBuffer::new_multisampled(100, 70, SampleCount::X2);
+        let mut parallel = FrameBuffer::new_multisampled(100, 70, SampleCount::X2);
+        single.clear(0xFF102030, 1.0, 0);
+        parallel.clear(0xFF102030, 1.0, 0);
+
+        fs.dispatch(&state, &mut single, &output, &indices);
+        fs.dispatch_parallel(&state, &mut parallel, &output, &indices, 3);
+
+        assert!(single.colour.as_slice().iter().any(|colour| *colour != 0xFF102030));
+        assert!(single.colour.as_slice() == parallel.colour.as_slice());
+    }
+
+    #[test]
+    fn lines_and_points_cover_one_pixel_wide() {
+        let fs = FragmentShader::new(White, ());
+        let mut target = FrameBuffer::new(8, 8);
+        target.clear(0xFF000000, 1.0, 0);
+
+        //Across the whole screen through the centres of row 3
+        let line = VertexOutput {
+            primitive: PrimitiveType::Line,
+            ndc_positions: vec![Vec4::new(-1.0, 0.125, 0.5, 1.0), Vec4::new(1.0, 0.125, 0.5, 1.0)],
+            depths: vec![0.5; 2],
+            varyings: vec![0.0; 2]
+        };
+        fs.dispatch(&RenderState::default(), &mut target, &line, &[0, 1]);
+
+        //On the centre of pixel (5, 1)
+        let point = VertexOutput {
+            primitive: PrimitiveType::Point,
+            ndc_positions: vec![Vec4::new(0.375, 0.625, 0.5, 1.0)],
+            depths: vec![0.5],
+            varyings: vec![0.0]
+        };
+        fs.dispatch(&RenderState::default(), &mut target, &point, &[0]);
+
+        for j in 0..8 {
+            for i in 0..8 {
+                let expected = j == 3 || (i == 5 && j == 1);
+                assert_eq!(target.colour.read(i, j) == 0xFFFFFFFF, expected, "pixel ({i}, {j})");
+            }
+        }
+    }
+
+    //White, counting its invocations
+    #[derive(Default)]
+    struct Counted(std::cell::Cell<usize>);
+
+    impl FragmentProgram for Counted {
+        type Uniforms = ();
+        type Varyings = f32;
+
+        fn shade(&self, _uniforms: &(), _fragment: &Fragment<f32>) -> Vec4 {
+            self.0.set(self.0.get() + 1);
+            Vec4::ONE
+        }
+    }
+
+    #[test]
+    fn msaa_lines_shade_each_pixel_once() {
+        let fs = FragmentShader::new(Counted::default(), ());
+        let mut target = FrameBuffer::new_multisampled(8, 8, SampleCount::X4);
+        target.clear(0xFF000000, 1.0, 0);
+
+        //Row 3 again, the diagonal between the halves of the quad runs through every pixel of it
+        let line = VertexOutput {
+            primitive: PrimitiveType::Line,
+            ndc_positions: vec![Vec4::new(-1.0, 0.125, 0.5, 1.0), Vec4::new(1.0, 0.125, 0.5, 1.0)],
+            depths: vec![0.5; 2],
+            varyings: vec![0.0; 2]
+        };
+        fs.dispatch(&RenderState::default(), &mut target, &line, &[0, 1]);
+
+        let mut resolved = Texture::new(8, 8);
+        target.resolve(&mut resolved);
+
+        let touched = resolved.as_slice().iter().filter(|colour| **colour != 0xFF000000).count();
+        assert_eq!(touched, 8);
+        assert_eq!(fs.program.0.get(), touched);
+    }
+
+    #[test]
+    fn wide_lines_are_depth_tested() {
+        let fs = FragmentShader::new(White, ());
+        let mut target = FrameBuffer::new(8, 8);
+        target.clear(0xFF000000, 1.0, 0);
+
+        //Left half of the screen is closer than the line
+        let mut occluder = half_screen();
+        occluder.ndc_positions[2] = Vec4::new(0.0, -1.0, 0.25, 1.0);
+        occluder.ndc_positions.iter_mut().for_each(|position| position.z = 0.25);
+        occluder.ndc_positions.push(Vec4::new(0.0, 1.0, 0.25, 1.0));
+        occluder.depths = vec![0.25; 4];
+        occluder.varyings.push(0.0);
+
+        let depth_only = RenderState { colour_mask: ColourMask::NONE, ..Default::default() };
+        fs.dispatch(&depth_only, &mut target, &occluder, &[0, 1, 2, 0, 2, 3]);
+
+        //Three pixels wide around row 3
+        let line = VertexOutput {
+            primitive: PrimitiveType::Line,
+            ndc_positions: vec![Vec4::new(-1.0, 0.125, 0.5, 1.0), Vec4::new(1.0, 0.125, 0.5, 1.0)],
+            depths: vec![0.5; 2],
+            varyings: vec![0.0; 2]
+        };
+        let wide = RenderState { line_width: 3.0, ..Default::default() };
+        fs.dispatch(&wide, &mut target, &line, &[0, 1]);
+
+        for j in 0..8 {
+            for i in 0..8 {
+                let expected = (2..=4).contains(&j) && i >= 4;
+                assert_eq!(target.colour.read(i, j) == 0xFFFFFFFF, expected, "pixel ({i}, {j})");
+            }
+        }
+    }
+
+    #[test]
+    fn fill_modes_draw_edges() {
+        let fs = FragmentShader::new(White, ());
+        let green = |target: &FrameBuffer, i: usize, j: usize| (target.colour.read(i, j) >> 8) & 0xFF;
+
+        let mut wireframe = FrameBuffer::new(8, 8);
+        wireframe.clear(0xFF000000, 1.0, 0);
+        fs.dispatch(&RenderState { fill_mode: FillMode::Wireframe, ..Default::default() }, &mut wireframe, &half_screen(), &[0, 1, 2]);
+
+        //Diagonal edge drawn, inside left empty
+        assert_eq!(green(&wireframe, 3, 3), 0xFF);
+        assert_eq!(green(&wireframe, 2, 5), 0);
+
+        let mut overlay = FrameBuffer::new(8, 8);
+        overlay.clear(0xFF000000, 1.0, 0);
+        let state = RenderState { fill_mode: FillMode::SolidWireframe { colour: Vec4::new(1.0, 0.0, 0.0, 1.0) }, ..Default::default() };
+        fs.dispatch(&state, &mut overlay, &half_screen(), &[0, 1, 2]);
+
+        //Interior keeps the shaded colour, pixels near the diagonal are tinted towards the edge colour
+        assert_eq!(overlay.colour.read(2, 5), 0xFFFFFFFF);
+        assert!(green(&overlay, 3, 4) < 0xFF);
+        assert_eq!((overlay.colour.read(3, 4) >> 16) & 0xFF, 0xFF);
+    }
+
+    #[test]
+    fn depth_bias_resolves_coplanar_geometry() {
+        let mut target = FrameBuffer::new(8, 8);
+        target.clear(0xFF000000, 1.0, 0);
+
+        FragmentShader::new(White, ()).dispatch(&RenderState::default(), &mut target, &half_screen(), &[0, 1, 2]);
+
+        //Same depth fails the default less test unless it is pulled towards the camera
+        let decal = FragmentShader::new(Translucent, ());
+        decal.dispatch(&RenderState::default(), &mut target, &half_screen(), &[0, 1, 2]);
+        assert_eq!(target.colour.read(1, 6), 0xFFFFFFFF);
+
+        let biased = RenderState { depth_bias: DepthBias { constant: -4.0, ..Default::default() }, ..Default::default() };
+        decal.dispatch(&biased, &mut target, &half_screen(), &[0, 1, 2]);
+        assert_ne!(target.colour.read(1, 6), 0xFFFFFFFF);
+
+        //Past the far plane, kept by the clipper and clamped instead of discarded
+        let far = ColourVertexInput {
+            positions: vec![Vec3::new(-1.0, 1.0, 1.5), Vec3::new(-1.0, -1.0, 1.5), Vec3::new(1.0, -1.0, 1.5)],
+            colours: vec![Vec4::ONE; 3]
+        };
+        let vs = VertexShader::<ColourVertexProgram>::default();
+
+        let clamped = RenderState { depth_clamp: true, depth_function: CompareFunction::LessEqual, ..Default::default() };
+        let (output, indices) = vs.dispatch(&clamped, PrimitiveTopology::TriangleList, &far, &[0, 1, 2]);
+
+        let mut target = FrameBuffer::new(8, 8);
+        target.clear(0xFF000000, 1.0, 0);
+        FragmentShader::<ColourFragmentProgram>::default().dispatch(&clamped, &mut target, &output, &indices);
+
+        assert_eq!(target.colour.read(1, 6), 0xFFFFFFFF);
+        assert_eq!(target.depth.read(1, 6), 1.0);
+
+        let (_, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &far, &[0, 1, 2]);
+        assert!(indices.is_empty());
+    }
+
+    #[test]
+    fn f64_depth_separates_distant_surfaces() {
+        let camera = Camera {
+            projection: Projection::Perspective { fov: 1.0 }, aspect_ratio: 1.0, near: 0.1, far: 1.0e5, ..Default::default()
+        };
+
+        let mut vs = VertexShader::<ColourVertexProgram>::default();
+        (vs.uniforms.view, vs.uniforms.projection) = camera.generate_view_projection();
+        vs.clip.set_camera(&camera);
+
+        //Screen filling quads half a unit apart, the far one drawn first
+        let quad = |distance: f32, colour: Vec4| ColourVertexInput {
+            positions: vec![
+                Vec3::new(-1.0e4, 1.0e4, -distance), Vec3::new(-1.0e4, -1.0e4, -distance),
+                Vec3::new(1.0e4, -1.0e4, -distance), Vec3::new(1.0e4, 1.0e4, -distance)
+            ],
+            colours: vec![colour; 4]
+        };
+        let far = quad(5000.5, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        let near = quad(5000.0, Vec4::new(0.0, 1.0, 0.0, 1.0));
+
+        let draw = |depth_format: DepthFormat| {
+            let mut target = FrameBuffer::new_with_depth_format(8, 8, SampleCount::X1, depth_format);
+            target.clear(0xFF000000, 1.0, 0);
+
+            for surface in [&far, &near] {
+                let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, surface, &[0, 1, 2, 2, 3, 0]);
+                FragmentShader::<ColourFragmentProgram>::default().dispatch(&RenderState::default(), &mut target, &output, &indices);
+            }
+            //True if the near surface is the one left, interpolation keeps the colours just short of 0xFF
+            let colour = target.colour.read(4, 4);
+            (colour >> 8) & 0xFF > (colour >> 16) & 0xFF
+        };
+
+        //Both round to the same f32 depth, so the near surface loses the less test
+        assert!(!draw(DepthFormat::F32));
+        assert!(draw(DepthFormat::F64));
+    }
+}
@@ -2,6 +2,11 @@ pub mod data;
 pub mod debug;
 pub mod vertex;
 pub mod fragment;
+pub mod primitive;
+pub mod programs;
+pub mod raster;
+pub mod state;
+pub mod culling;
 
-
-
+#[cfg(test)]
+pub mod testing;
@@ -0,0 +1,175 @@
+//Index that ends the current strip or fan, the next index starts a new one
+pub const PRIMITIVE_RESTART: usize = usize::MAX;
+
+//How an index buffer is read into primitives
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveTopology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+    LineList,
+    LineStrip,
+    PointList
+}
+
+//Primitive the topology is assembled into
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveType {
+    #[default]
+    Triangle,
+    Line,
+    Point
+}
+
+impl PrimitiveType {
+    pub fn vertex_count(&self) -> usize {
+        match self {
+            PrimitiveType::Triangle => 3,
+            PrimitiveType::Line => 2,
+            PrimitiveType::Point => 1
+        }
+    }
+}
+
+impl PrimitiveTopology {
+
+    pub fn primitive_type(&self) -> PrimitiveType {
+        match self {
+            PrimitiveTopology::TriangleList | PrimitiveTopology::TriangleStrip | PrimitiveTopology::TriangleFan => PrimitiveType::Triangle,
+            PrimitiveTopology::LineList | PrimitiveTopology::LineStrip => PrimitiveType::Line,
+            PrimitiveTopology::PointList => PrimitiveType::Point
+        }
+    }
+
+    //Expands the index buffer into a list of independent primitives of primitive_type()
+    //Strips keep the winding of their first triangle, incomplete primitives are dropped
+    //The provoking vertex comes first: vertex i for the i-th triangle of a strip or fan, like the first vertex convention
+    pub fn assemble(&self, indices: &[usize]) -> Vec<usize> {
+
+        let mut out = Vec::new();
+
+        for run in indices.split(|index| *index == PRIMITIVE_RESTART) {
+            match self {
+                PrimitiveTopology::TriangleList => {
+                    out.extend(run.chunks_exact(3).flatten());
+                }
+                PrimitiveTopology::TriangleStrip => {
+                    for (i, window) in run.windows(3).enumerate() {
+                        if i % 2 == 0 {
+                            out.extend([window[0], window[1], window[2]]);
+                        } else {
+                            out.extend([window[0], window[2], window[1]]);
+                        }
+                    }
+                }
+                PrimitiveTopology::TriangleFan => {
+                    for window in run.windows(2).skip(1) {
+                        out.extend([window[0], window[1], run[0]]);
+                    }
+                }
+                PrimitiveTopology::LineList => {
+                    out.extend(run.chunks_exact(2).flatten());
+                }
+                PrimitiveTopology::LineStrip => {
+                    out.extend(run.windows(2).flatten());
+                }
+                PrimitiveTopology::PointList => {
+                    out.extend(run);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4};
+    use crate::math::colour;
+    use crate::math::varying::Flat;
+    use crate::texture::FrameBuffer;
+    use crate::renderer::fragment::{Fragment, FragmentProgram, FragmentShader};
+    use crate::renderer::state::RenderState;
+    use crate::renderer::vertex::{VertexProgram, VertexShader};
+    use super::{PrimitiveTopology, PRIMITIVE_RESTART};
+
+    //Positions are already in clip space, every vertex carries its index as a flat varying
+    #[derive(Default)]
+    struct VertexId;
+
+    impl VertexProgram for VertexId {
+        type Uniforms = ();
+        type Input = Vec<Vec3>;
+        type Varyings = Flat<u32>;
+
+        fn shade(&self, _uniforms: &(), input: &Vec<Vec3>, vertex_id: usize) -> (Vec4, Flat<u32>) {
+            (input[vertex_id].extend(1.0), Flat(vertex_id as u32))
+        }
+    }
+
+    #[derive(Default)]
+    struct IdColour;
+
+    impl FragmentProgram for IdColour {
+        type Uniforms = ();
+        type Varyings = Flat<u32>;
+
+        fn shade(&self, _uniforms: &(), fragment: &Fragment<Flat<u32>>) -> Vec4 {
+            Vec4::new(fragment.varyings.0 as f32 * 0.25, 0.0, 0.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn strips_alternate_winding() {
+        let triangles = PrimitiveTopology::TriangleStrip.assemble(&[0, 1, 2, 3, 4]);
+        assert_eq!(triangles, vec![0, 1, 2, 1, 3, 2, 2, 3, 4]);
+    }
+
+    #[test]
+    fn restart_splits_strips_and_fans() {
+        let strip = PrimitiveTopology::TriangleStrip.assemble(&[0, 1, 2, PRIMITIVE_RESTART, 3, 4, 5, 6]);
+        assert_eq!(strip, vec![0, 1, 2, 3, 4, 5, 4, 6, 5]);
+
+        let fan = PrimitiveTopology::TriangleFan.assemble(&[0, 1, 2, 3, PRIMITIVE_RESTART, 4, 5, 6]);
+        assert_eq!(fan, vec![1, 2, 0, 2, 3, 0, 5, 6, 4]);
+
+        let lines = PrimitiveTopology::LineStrip.assemble(&[0, 1, 2, PRIMITIVE_RESTART, 3, 4]);
+        assert_eq!(lines, vec![0, 1, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn flat_varyings_follow_the_first_vertex_convention() {
+        //Corners of the screen, top left, bottom left, top right, bottom right
+        let quad = vec![Vec3::new(-1.0, 1.0, 0.5), Vec3::new(-1.0, -1.0, 0.5), Vec3::new(1.0, 1.0, 0.5), Vec3::new(1.0, -1.0, 0.5)];
+        let id = |id: u32| colour::vec4_to_hex(Vec4::new(id as f32 * 0.25, 0.0, 0.0, 1.0));
+
+        let draw = |topology: PrimitiveTopology, indices: &[usize]| {
+            let state = RenderState::default();
+            let (output, indices) = VertexShader::<VertexId>::default().dispatch(&state, topology, &quad, indices);
+
+            let mut target = FrameBuffer::new(8, 8);
+            target.clear(0, 1.0, 0);
+            FragmentShader::<IdColour>::default().dispatch(&state, &mut target, &output, &indices);
+            target
+        };
+
+        //Triangle i of a strip takes vertex i, the upper left half comes from 0 and the lower right from 1
+        let strip = draw(PrimitiveTopology::TriangleStrip, &[0, 1, 2, 3]);
+        assert_eq!(strip.colour.read(1, 1), id(0));
+        assert_eq!(strip.colour.read(6, 6), id(1));
+
+        //Fans too, the hub never provokes past the first triangle
+        let fan = draw(PrimitiveTopology::TriangleFan, &[0, 1, 3, 2]);
+        assert_eq!(fan.colour.read(1, 6), id(1));
+        assert_eq!(fan.colour.read(6, 1), id(3));
+    }
+
+    #[test]
+    fn lists_drop_incomplete_primitives() {
+        assert_eq!(PrimitiveTopology::TriangleList.assemble(&[0, 1, 2, 3, 4]), vec![0, 1, 2]);
+        assert_eq!(PrimitiveTopology::LineList.assemble(&[0, 1, 2]), vec![0, 1]);
+        assert_eq!(PrimitiveTopology::PointList.assemble(&[0, PRIMITIVE_RESTART, 2]), vec![0, 2]);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::math::varying::impl_varying;
+use crate::texture::Sampler;
+use crate::texture::Texture;
+use super::data::VertexInput;
+use super::fragment::Fragment;
+use super::fragment::FragmentProgram;
+use super::vertex::VertexProgram;
+
+#[derive(Default)]
+pub struct TransformUniforms {
+    pub model: glam::Mat4,
+    pub view: glam::Mat4,
+    pub projection: glam::Mat4
+}
+
+#[derive(Default)]
+pub struct TextureUniforms {
+    pub texture: Texture,
+    pub sampler: Sampler
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UnlitVaryings {
+    pub colour: glam::Vec3,
+    pub uv: glam::Vec2
+}
+
+impl_varying!(UnlitVaryings { colour, uv });
+
+//MVP transform, passes vertex colours and uvs through
+#[derive(Default)]
+pub struct UnlitVertexProgram;
+
+impl VertexProgram for UnlitVertexProgram {
+    type Uniforms = TransformUniforms;
+    type Input = VertexInput;
+    type Varyings = UnlitVaryings;
+
+    fn shade(&self, uniforms: &TransformUniforms, input: &VertexInput, vertex_id: usize) -> (glam::Vec4, UnlitVaryings) {
+        let mvp = uniforms.projection * uniforms.view * uniforms.model;
+
+        (
+            mvp.mul_vec4(input.positions[vertex_id].extend(1.0)),
+            UnlitVaryings { colour: input.colours[vertex_id], uv: input.uvs[vertex_id] }
+        )
+    }
+}
+
+//Vertex colour multiplied by the texture colour
+#[derive(Default)]
+pub struct UnlitFragmentProgram;
+
+impl FragmentProgram for UnlitFragmentProgram {
+    type Uniforms = TextureUniforms;
+    type Varyings = UnlitVaryings;
+
+    fn shade(&self, uniforms: &TextureUniforms, fragment: &Fragment<UnlitVaryings>) -> glam::Vec4 {
+        let texel = uniforms.sampler.sample(&uniforms.texture, fragment.varyings.uv);
+        (fragment.varyings.colour * texel.truncate()).extend(texel.w)
+    }
+}
+
+//World space positions with a colour per vertex, used for debug lines and points
+#[derive(Default)]
+pub struct ColourVertexInput {
+    pub positions: Vec<glam::Vec3>,
+    pub colours: Vec<glam::Vec4>
+}
+
+//MVP transform, passes the vertex colour through
+#[derive(Default)]
+pub struct ColourVertexProgram;
+
+impl VertexProgram for ColourVertexProgram {
+    type Uniforms = TransformUniforms;
+    type Input = ColourVertexInput;
+    type Varyings = glam::Vec4;
+
+    fn shade(&self, uniforms: &TransformUniforms, input: &ColourVertexInput, vertex_id: usize) -> (glam::Vec4, glam::Vec4) {
+        let mvp = uniforms.projection * uniforms.view * uniforms.model;
+        (mvp.mul_vec4(input.positions[vertex_id].extend(1.0)), input.colours[vertex_id])
+    }
+}
+
+//Outputs the interpolated vertex colour
+#[derive(Default)]
+pub struct ColourFragmentProgram;
+
+impl FragmentProgram for ColourFragmentProgram {
+    type Uniforms = ();
+    type Varyings = glam::Vec4;
+
+    fn shade(&self, _uniforms: &(), fragment: &Fragment<glam::Vec4>) -> glam::Vec4 {
+        fragment.varyings
+    }
+}
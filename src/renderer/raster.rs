@@ -0,0 +1,443 @@
+use std::ops::Range;
+
+use glam::Vec2;
+use glam::Vec3;
+
+//Screen positions are snapped to 1/256th of a pixel
+pub const SUBPIXEL_BITS: u32 = 8;
+pub const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+
+pub fn to_fixed(v: f32) -> i64 {
+    (v * SUBPIXEL_ONE as f32).round() as i64
+}
+
+//Fixed point position of a sample, offset is inside the pixel
+pub fn sample_to_fixed(i: usize, offset: f32) -> i64 {
+    ((i as i64) << SUBPIXEL_BITS) + to_fixed(offset)
+}
+
+//Most samples a pixel can have, see SampleCount
+pub const MAX_SAMPLES: usize = 8;
+
+//Side of the square blocks that are rejected or accepted as a whole before any quad is stepped
+pub const BLOCK_SIZE: usize = 8;
+
+//Edge values of one sample for the four pixels of a 2x2 quad, lanes are (i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)
+//Kept as a plain array so the per lane loops get vectorized
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Lanes(pub [i64; 4]);
+
+impl Lanes {
+    fn add(self, step: i64) -> Self {
+        let mut out = self.0;
+        for lane in out.iter_mut() { *lane += step; }
+        Self(out)
+    }
+
+    //Bit per lane whose value is >= threshold
+    fn mask_at_least(&self, threshold: i64) -> u8 {
+        let mut mask = 0;
+        for (lane, value) in self.0.iter().enumerate() {
+            mask |= ((*value >= threshold) as u8) << lane;
+        }
+        mask
+    }
+}
+
+//value(x, y) = a * x + b * y + c, oriented so the inside is positive
+#[derive(Debug, Clone, Copy)]
+struct EdgeEquation {
+    a: i64,
+    b: i64,
+    c: i64,
+    //Subtracted from the value, 0 for top and left edges and 1 otherwise
+    bias: i64
+}
+
+impl EdgeEquation {
+
+    fn new(start: (i64, i64), end: (i64, i64), orientation: i64) -> Self {
+        let a = (start.1 - end.1) * orientation;
+        let b = (end.0 - start.0) * orientation;
+        let c = -(a * start.0 + b * start.1);
+
+        //(a, b) is the inward facing gradient, y points down the screen
+        let top_left = a > 0 || (a == 0 && b > 0);
+        Self { a, b, c, bias: if top_left { 0 } else { 1 } }
+    }
+
+    fn evaluate(&self, x: i64, y: i64) -> i64 {
+        self.a * x + self.b * y + self.c
+    }
+
+    //Largest and smallest value over a rectangle, found at the corners picked by the gradient
+    fn extremes(&self, min: (i64, i64), max: (i64, i64)) -> (i64, i64) {
+        let (high_x, low_x) = if self.a >= 0 { (max.0, min.0) } else { (min.0, max.0) };
+        let (high_y, low_y) = if self.b >= 0 { (max.1, min.1) } else { (min.1, max.1) };
+        (self.evaluate(low_x, low_y), self.evaluate(high_x, high_y))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockCoverage {
+    Outside,
+    Partial,
+    Inside
+}
+
+//Triangle prepared for coverage tests, edge functions are evaluated exactly on snapped vertices
+//so two triangles sharing an edge never both claim, or both miss, a sample on it
+#[derive(Debug, Clone, Copy)]
+pub struct TriangleSetup {
+    vertices: [(i64, i64); 3],
+    //Edge k is opposite to vertex k
+    edges: [EdgeEquation; 3],
+    area: i64
+}
+
+impl TriangleSetup {
+
+    //Returns None for triangles that have no area once snapped
+    pub fn new(v1: Vec2, v2: Vec2, v3: Vec2) -> Option<Self> {
+
+        let vertices = [v1, v2, v3].map(|v| (to_fixed(v.x), to_fixed(v.y)));
+
+        let (a, b, c) = (vertices[0], vertices[1], vertices[2]);
+        let area = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+        if area == 0 { return None; }
+
+        let orientation = area.signum();
+        let edges = [(b, c), (c, a), (a, b)].map(|(start, end)| EdgeEquation::new(start, end, orientation));
+
+        Some(Self { vertices, edges, area: area * orientation })
+    }
+
+    //Oriented edge values at a fixed point position, the i-th one is the weight of the i-th vertex times twice the area
+    pub fn evaluate(&self, x: i64, y: i64) -> [i64; 3] {
+        self.edges.map(|edge| edge.evaluate(x, y))
+    }
+
+    //Top-left rule, samples exactly on an edge only belong to the triangle if it is a top or left edge
+    pub fn is_inside(&self, values: &[i64; 3]) -> bool {
+        values.iter().zip(&self.edges).all(|(value, edge)| value - edge.bias >= 0)
+    }
+
+    pub fn barycentric(&self, values: &[i64; 3]) -> Vec3 {
+        Vec3::new(values[0] as f32, values[1] as f32, values[2] as f32) / self.area as f32
+    }
+
+    //Signed distance in pixels from each edge, positive inside
+    pub fn edge_distances(&self, values: &[i64; 3]) -> Vec3 {
+        let distance = |k: usize| {
+            let edge = &self.edges[k];
+            values[k] as f32 / ((edge.a * edge.a + edge.b * edge.b) as f32).sqrt() / SUBPIXEL_ONE as f32
+        };
+
+        Vec3::new(distance(0), distance(1), distance(2))
+    }
+
+    //Pixels that can hold a covered sample, clamped to the target size
+    pub fn pixel_bounds(&self, width: usize, height: usize) -> Option<(Range<usize>, Range<usize>)> {
+
+        let min_x = self.vertices.iter().map(|v| v.0).min()?;
+        let min_y = self.vertices.iter().map(|v| v.1).min()?;
+        let max_x = self.vertices.iter().map(|v| v.0).max()?;
+        let max_y = self.vertices.iter().map(|v| v.1).max()?;
+
+        let start_x = (min_x >> SUBPIXEL_BITS).clamp(0, width as i64) as usize;
+        let start_y = (min_y >> SUBPIXEL_BITS).clamp(0, height as i64) as usize;
+        let end_x = ((max_x >> SUBPIXEL_BITS) + 1).clamp(0, width as i64) as usize;
+        let end_y = ((max_y >> SUBPIXEL_BITS) + 1).clamp(0, height as i64) as usize;
+
+        if start_x >= end_x || start_y >= end_y { return None; }
+        Some((start_x..end_x, start_y..end_y))
+    }
+
+    //Conservative test of every sample a block of pixels can hold
+    pub fn classify_block(&self, x_range: &Range<usize>, y_range: &Range<usize>) -> BlockCoverage {
+
+        let min = ((x_range.start as i64) << SUBPIXEL_BITS, (y_range.start as i64) << SUBPIXEL_BITS);
+        let max = ((x_range.end as i64) << SUBPIXEL_BITS, (y_range.end as i64) << SUBPIXEL_BITS);
+
+        let mut inside = true;
+        for edge in &self.edges {
+            let (low, high) = edge.extremes(min, max);
+            if high - edge.bias < 0 { return BlockCoverage::Outside; }
+            inside &= low - edge.bias >= 0;
+        }
+
+        if inside { BlockCoverage::Inside } else { BlockCoverage::Partial }
+    }
+
+    //Edge values of the quad whose top left pixel holds the sample at (x, y)
+    fn quad_lanes(&self, x: i64, y: i64) -> [Lanes; 3] {
+        self.edges.map(|edge| {
+            let value = edge.evaluate(x, y);
+            let (step_x, step_y) = (edge.a * SUBPIXEL_ONE, edge.b * SUBPIXEL_ONE);
+            Lanes([value, value + step_x, value + step_y, value + step_x + step_y])
+        })
+    }
+
+    //Walks the region block by block and quad by quad, stepping the edge values incrementally
+    //visit gets the pixel, a bit per sample that lies inside the triangle and the edge values of every sample
+    pub fn for_each_covered<F>(&self, x_range: Range<usize>, y_range: Range<usize>, sample_offsets: &[(i64, i64)], mut visit: F)
+    where F: FnMut(usize, usize, u8, &[[i64; 3]; MAX_SAMPLES])
+    {
+        debug_assert!(sample_offsets.len() <= MAX_SAMPLES);
+
+        let quad_steps = self.edges.map(|edge| 2 * edge.a * SUBPIXEL_ONE);
+        let mut values = [[0i64; 3]; MAX_SAMPLES];
+
+        let block_start = |start: usize| start - start % BLOCK_SIZE;
+
+        for block_y in (block_start(y_range.start)..y_range.end).step_by(BLOCK_SIZE) {
+            for block_x in (block_start(x_range.start)..x_range.end).step_by(BLOCK_SIZE) {
+
+                let block_columns = block_x.max(x_range.start)..(block_x + BLOCK_SIZE).min(x_range.end);
+                let block_rows = block_y.max(y_range.start)..(block_y + BLOCK_SIZE).min(y_range.end);
+
+                let coverage = self.classify_block(&block_columns, &block_rows);
+                if coverage == BlockCoverage::Outside { continue; }
+
+                //Quads sit on even coordinates, lanes falling outside the block are skipped
+                let quad_x = block_columns.start & !1;
+
+                for quad_y in ((block_rows.start & !1)..block_rows.end).step_by(2) {
+
+                    let mut lanes = [[Lanes::default(); 3]; MAX_SAMPLES];
+                    for (sample, (offset_x, offset_y)) in sample_offsets.iter().enumerate() {
+                        lanes[sample] = self.quad_lanes(((quad_x as i64) << SUBPIXEL_BITS) + offset_x, ((quad_y as i64) << SUBPIXEL_BITS) + offset_y);
+                    }
+
+                    for x in (quad_x..block_columns.end).step_by(2) {
+
+                        //Bit per sample for every lane
+                        let mut masks = [0u8; 4];
+                        for (sample, sample_lanes) in lanes.iter().enumerate().take(sample_offsets.len()) {
+                            let inside = match coverage {
+                                BlockCoverage::Inside => 0b1111,
+                                _ => sample_lanes.iter().zip(&self.edges).fold(0b1111, |mask, (edge_lanes, edge)| mask & edge_lanes.mask_at_least(edge.bias))
+                            };
+
+                            for (lane, mask) in masks.iter_mut().enumerate() {
+                                *mask |= ((inside >> lane) & 1) << sample;
+                            }
+                        }
+
+                        for (lane, mask) in masks.iter().enumerate() {
+                            let (i, j) = (x + lane % 2, quad_y + lane / 2);
+                            if *mask == 0 || !block_columns.contains(&i) || !block_rows.contains(&j) { continue; }
+
+                            for (sample, sample_lanes) in lanes.iter().enumerate().take(sample_offsets.len()) {
+                                values[sample] = [sample_lanes[0].0[lane], sample_lanes[1].0[lane], sample_lanes[2].0[lane]];
+                            }
+
+                            visit(i, j, *mask, &values);
+                        }
+
+                        for sample_lanes in lanes.iter_mut().take(sample_offsets.len()) {
+                            for (edge_lanes, step) in sample_lanes.iter_mut().zip(quad_steps) {
+                                *edge_lanes = edge_lanes.add(step);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use crate::math;
+    use crate::texture::SampleCount;
+    use crate::renderer::testing::Lcg;
+    use super::{sample_to_fixed, to_fixed, TriangleSetup};
+
+    const SIZE: usize = 32;
+
+    //How many triangles claim each pixel centre
+    fn coverage_counts(triangles: &[[Vec2; 3]]) -> Vec<u32> {
+        let mut counts = vec![0; SIZE * SIZE];
+
+        for triangle in triangles {
+            let Some(setup) = TriangleSetup::new(triangle[0], triangle[1], triangle[2]) else { continue; };
+            let Some((x_range, y_range)) = setup.pixel_bounds(SIZE, SIZE) else { continue; };
+
+            setup.for_each_covered(x_range, y_range, &[(to_fixed(0.5), to_fixed(0.5))], |i, j, _, _| {
+                counts[j * SIZE + i] += 1;
+            });
+        }
+
+        counts
+    }
+
+    fn random_triangles(count: usize, extent: f32) -> Vec<[Vec2; 3]> {
+        let mut lcg = Lcg::new(99);
+        let mut random = move || lcg.next_f32() * extent;
+
+        (0..count).map(|_| [
+            Vec2::new(random(), random()),
+            Vec2::new(random(), random()),
+            Vec2::new(random(), random())
+        ]).collect()
+    }
+
+    //Grid over [2, 30] with jittered inner vertices, some of them landing exactly on pixel centres
+    fn jittered_grid() -> Vec<[Vec2; 3]> {
+        let cells = 7;
+        let step = 28.0 / cells as f32;
+        let mut lcg = Lcg::new(12345);
+
+        let mut vertices = Vec::new();
+        for y in 0..=cells {
+            for x in 0..=cells {
+                let seed = lcg.next_u32();
+                let jitter = Vec2::new(((seed >> 16) % 7) as f32 - 3.0, ((seed >> 8) % 7) as f32 - 3.0) * 0.25;
+
+                let border = x == 0 || y == 0 || x == cells || y == cells;
+                let on_centre = seed.is_multiple_of(2);
+                let position = Vec2::new(2.0 + x as f32 * step, 2.0 + y as f32 * step);
+
+                vertices.push(
+                    if border { position }
+                    else if on_centre { (position + jitter).floor() + Vec2::splat(0.5) }
+                    else { position + jitter }
+                );
+            }
+        }
+
+        let index = |x: usize, y: usize| vertices[y * (cells + 1) + x];
+
+        let mut triangles = Vec::new();
+        for y in 0..cells {
+            for x in 0..cells {
+                //Alternate the diagonal and the winding so both orientations are exercised
+                if (x + y).is_multiple_of(2) {
+                    triangles.push([index(x, y), index(x, y + 1), index(x + 1, y + 1)]);
+                    triangles.push([index(x + 1, y + 1), index(x + 1, y), index(x, y)]);
+                } else {
+                    triangles.push([index(x, y), index(x + 1, y), index(x, y + 1)]);
+                    triangles.push([index(x + 1, y), index(x + 1, y + 1), index(x, y + 1)]);
+                }
+            }
+        }
+
+        triangles
+    }
+
+    #[test]
+    fn stepping_matches_direct_evaluation() {
+        let positions = SampleCount::X4.positions();
+        let offsets: Vec<(i64, i64)> = positions.iter().map(|(x, y)| (to_fixed(*x), to_fixed(*y))).collect();
+
+        for triangle in random_triangles(200, 40.0) {
+            let Some(setup) = TriangleSetup::new(triangle[0], triangle[1], triangle[2]) else { continue; };
+            let Some((x_range, y_range)) = setup.pixel_bounds(SIZE, SIZE) else { continue; };
+
+            let mut visited = vec![0u8; SIZE * SIZE];
+            setup.for_each_covered(x_range.clone(), y_range.clone(), &offsets, |i, j, mask, values| {
+                for (sample, (x, y)) in positions.iter().enumerate() {
+                    assert_eq!(values[sample], setup.evaluate(sample_to_fixed(i, *x), sample_to_fixed(j, *y)));
+                }
+                visited[j * SIZE + i] = mask;
+            });
+
+            for j in y_range {
+                for i in x_range.clone() {
+                    let expected = positions.iter().enumerate().fold(0u8, |mask, (sample, (x, y))| {
+                        let inside = setup.is_inside(&setup.evaluate(sample_to_fixed(i, *x), sample_to_fixed(j, *y)));
+                        mask | ((inside as u8) << sample)
+                    });
+                    assert_eq!(visited[j * SIZE + i], expected, "pixel {i}, {j}");
+                }
+            }
+        }
+    }
+
+    //cargo test --release -- --ignored --nocapture bench_coverage
+    #[test]
+    #[ignore]
+    fn bench_coverage() {
+        const WIDTH: usize = 640;
+        const HEIGHT: usize = 480;
+
+        //Large triangles plus long thin slivers across the screen
+        let mut triangles = random_triangles(200, 480.0);
+        for k in 0..200 {
+            let y = k as f32 * 2.4;
+            triangles.push([Vec2::new(0.0, y), Vec2::new(640.0, 480.0 - y), Vec2::new(0.0, y + 1.5)]);
+        }
+
+        let rounds = 10;
+
+        //Per pixel loop with floating point barycentric weights
+        let timer = std::time::Instant::now();
+        let mut reference = 0usize;
+        for _ in 0..rounds {
+            for t in &triangles {
+                let bounds = math::generate_triangle_bounding_box(t[0], t[1], t[2]);
+                for j in bounds.start.y.min(HEIGHT as u32)..bounds.end.y.min(HEIGHT as u32) {
+                    for i in bounds.start.x.min(WIDTH as u32)..bounds.end.x.min(WIDTH as u32) {
+                        let point = Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
+                        reference += math::barycentric_weights(point, t[0], t[1], t[2]).is_some() as usize;
+                    }
+                }
+            }
+        }
+        let per_pixel = timer.elapsed();
+
+        //Block reject and incremental quad stepping
+        let timer = std::time::Instant::now();
+        let mut stepped = 0usize;
+        for _ in 0..rounds {
+            for t in &triangles {
+                let Some(setup) = TriangleSetup::new(t[0], t[1], t[2]) else { continue; };
+                let Some((x_range, y_range)) = setup.pixel_bounds(WIDTH, HEIGHT) else { continue; };
+                setup.for_each_covered(x_range, y_range, &[(to_fixed(0.5), to_fixed(0.5))], |_, _, _, _| stepped += 1);
+            }
+        }
+        let incremental = timer.elapsed();
+
+        println!("per pixel:   {:?} ({} samples)", per_pixel, reference);
+        println!("incremental: {:?} ({} samples)", incremental, stepped);
+        println!("speedup:     {:.2}x", per_pixel.as_secs_f64() / incremental.as_secs_f64());
+    }
+
+    #[test]
+    fn grid_is_watertight() {
+        let counts = coverage_counts(&jittered_grid());
+
+        for j in 0..SIZE {
+            for i in 0..SIZE {
+                let inside = (2..30).contains(&i) && (2..30).contains(&j);
+                assert_eq!(counts[j * SIZE + i], inside as u32, "pixel {i}, {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn fan_around_pixel_centre_covers_it_once() {
+        let centre = Vec2::new(16.5, 16.5);
+        let rim: Vec<Vec2> = (0..12).map(|k| {
+            let angle = k as f32 / 12.0 * std::f32::consts::TAU;
+            centre + Vec2::new(angle.cos(), angle.sin()) * 10.0
+        }).collect();
+
+        let triangles: Vec<[Vec2; 3]> = (0..12).map(|k| [centre, rim[k], rim[(k + 1) % 12]]).collect();
+        let counts = coverage_counts(&triangles);
+
+        assert_eq!(counts[16 * SIZE + 16], 1);
+        assert!(counts.iter().all(|count| *count <= 1));
+    }
+
+    #[test]
+    fn quad_diagonal_is_shaded_once() {
+        let quad = [Vec2::new(4.0, 4.0), Vec2::new(4.0, 20.0), Vec2::new(20.0, 20.0), Vec2::new(20.0, 4.0)];
+        let counts = coverage_counts(&[[quad[0], quad[1], quad[2]], [quad[2], quad[3], quad[0]]]);
+
+        assert_eq!(counts.iter().sum::<u32>(), 16 * 16);
+        assert!(counts.iter().all(|count| *count <= 1));
+    }
+}
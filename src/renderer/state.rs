@@ -0,0 +1,440 @@
+use glam::Vec4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    #[default]
+    None,
+    Front,
+    Back
+}
+
+//Winding of front facing triangles once projected, with y pointing up
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FrontFace {
+    #[default]
+    CounterClockwise,
+    Clockwise
+}
+
+impl FrontFace {
+    pub fn is_front(&self, clockwise: bool) -> bool {
+        match self {
+            FrontFace::CounterClockwise => !clockwise,
+            FrontFace::Clockwise => clockwise
+        }
+    }
+}
+
+//Comparison between an incoming value and the one stored in an attachment
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompareFunction {
+    Never,
+    #[default]
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always
+}
+
+impl CompareFunction {
+    pub fn compare<T: PartialOrd>(&self, incoming: T, stored: T) -> bool {
+        match self {
+            CompareFunction::Never => false,
+            CompareFunction::Less => incoming < stored,
+            CompareFunction::LessEqual => incoming <= stored,
+            CompareFunction::Equal => incoming == stored,
+            CompareFunction::NotEqual => incoming != stored,
+            CompareFunction::GreaterEqual => incoming >= stored,
+            CompareFunction::Greater => incoming > stored,
+            CompareFunction::Always => true
+        }
+    }
+}
+
+//Update applied to the stored stencil value
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StencilOperation {
+    #[default]
+    Keep,
+    Zero,
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    IncrementWrap,
+    DecrementWrap,
+    Invert
+}
+
+impl StencilOperation {
+    pub fn apply(&self, stored: u8, reference: u8) -> u8 {
+        match self {
+            StencilOperation::Keep => stored,
+            StencilOperation::Zero => 0,
+            StencilOperation::Replace => reference,
+            StencilOperation::IncrementClamp => stored.saturating_add(1),
+            StencilOperation::DecrementClamp => stored.saturating_sub(1),
+            StencilOperation::IncrementWrap => stored.wrapping_add(1),
+            StencilOperation::DecrementWrap => stored.wrapping_sub(1),
+            StencilOperation::Invert => !stored
+        }
+    }
+}
+
+//Stencil behaviour for one facing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilFaceState {
+    pub compare: CompareFunction,
+    pub fail: StencilOperation,
+    pub depth_fail: StencilOperation,
+    pub pass: StencilOperation
+}
+
+impl Default for StencilFaceState {
+    fn default() -> Self {
+        Self {
+            compare: CompareFunction::Always,
+            fail: StencilOperation::Keep,
+            depth_fail: StencilOperation::Keep,
+            pass: StencilOperation::Keep
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilState {
+    pub front: StencilFaceState,
+    pub back: StencilFaceState,
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self {
+            front: StencilFaceState::default(),
+            back: StencilFaceState::default(),
+            reference: 0,
+            read_mask: 0xFF,
+            write_mask: 0xFF
+        }
+    }
+}
+
+impl StencilState {
+    pub fn face(&self, front_facing: bool) -> &StencilFaceState {
+        if front_facing { &self.front } else { &self.back }
+    }
+
+    //The reference is the incoming value, so Less passes when reference < stored
+    pub fn test(&self, face: &StencilFaceState, stored: u8) -> bool {
+        face.compare.compare(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    //Returns the new stored value, only the bits in write_mask change
+    pub fn update(&self, operation: StencilOperation, stored: u8) -> u8 {
+        let value = operation.apply(stored, self.reference);
+        (value & self.write_mask) | (stored & !self.write_mask)
+    }
+}
+
+//Which channels of the colour attachment a draw is allowed to change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColourMask {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool
+}
+
+impl ColourMask {
+    pub const ALL: Self = Self { red: true, green: true, blue: true, alpha: true };
+    pub const NONE: Self = Self { red: false, green: false, blue: false, alpha: false };
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    //Merges two ARGB colours, taking the masked channels from incoming and the rest from stored
+    pub fn apply(&self, incoming: u32, stored: u32) -> u32 {
+        let channel = |enabled: bool, shift: u32| if enabled { 0xFF << shift } else { 0 };
+        let bits = channel(self.alpha, 24) | channel(self.red, 16) | channel(self.green, 8) | channel(self.blue, 0);
+
+        (incoming & bits) | (stored & !bits)
+    }
+}
+
+impl Default for ColourMask {
+    fn default() -> Self { Self::ALL }
+}
+
+//Multiplier applied to the source (fragment) or destination (attachment) colour before blending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColour,
+    OneMinusSrcColour,
+    DstColour,
+    OneMinusDstColour,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+    ConstantColour,
+    OneMinusConstantColour,
+    SrcAlphaSaturate
+}
+
+impl BlendFactor {
+    fn factor(&self, src: Vec4, dst: Vec4, constant: Vec4) -> Vec4 {
+        match self {
+            BlendFactor::Zero => Vec4::ZERO,
+            BlendFactor::One => Vec4::ONE,
+            BlendFactor::SrcColour => src,
+            BlendFactor::OneMinusSrcColour => Vec4::ONE - src,
+            BlendFactor::DstColour => dst,
+            BlendFactor::OneMinusDstColour => Vec4::ONE - dst,
+            BlendFactor::SrcAlpha => Vec4::splat(src.w),
+            BlendFactor::OneMinusSrcAlpha => Vec4::splat(1.0 - src.w),
+            BlendFactor::DstAlpha => Vec4::splat(dst.w),
+            BlendFactor::OneMinusDstAlpha => Vec4::splat(1.0 - dst.w),
+            BlendFactor::ConstantColour => constant,
+            BlendFactor::OneMinusConstantColour => Vec4::ONE - constant,
+            BlendFactor::SrcAlphaSaturate => {
+                let f = src.w.min(1.0 - dst.w);
+                Vec4::new(f, f, f, 1.0)
+            }
+        }
+    }
+}
+
+//How the weighted source and destination get combined, min and max ignore the factors
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOperation {
+    #[default]
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max
+}
+
+impl BlendOperation {
+    fn combine(&self, src: Vec4, src_factor: Vec4, dst: Vec4, dst_factor: Vec4) -> Vec4 {
+        match self {
+            BlendOperation::Add => src * src_factor + dst * dst_factor,
+            BlendOperation::Subtract => src * src_factor - dst * dst_factor,
+            BlendOperation::ReverseSubtract => dst * dst_factor - src * src_factor,
+            BlendOperation::Min => src.min(dst),
+            BlendOperation::Max => src.max(dst)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlendState {
+    pub colour_src: BlendFactor,
+    pub colour_dst: BlendFactor,
+    pub colour_operation: BlendOperation,
+    pub alpha_src: BlendFactor,
+    pub alpha_dst: BlendFactor,
+    pub alpha_operation: BlendOperation,
+    pub constant: Vec4
+}
+
+impl BlendState {
+    //Straight (non premultiplied) alpha
+    pub const ALPHA: Self = Self::new(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha, BlendFactor::One, BlendFactor::OneMinusSrcAlpha);
+
+    //Colours already multiplied by their alpha, see Texture::premultiply_alpha
+    pub const PREMULTIPLIED_ALPHA: Self = Self::new(BlendFactor::One, BlendFactor::OneMinusSrcAlpha, BlendFactor::One, BlendFactor::OneMinusSrcAlpha);
+
+    pub const ADDITIVE: Self = Self::new(BlendFactor::One, BlendFactor::One, BlendFactor::One, BlendFactor::One);
+
+    pub const MULTIPLY: Self = Self::new(BlendFactor::DstColour, BlendFactor::Zero, BlendFactor::DstAlpha, BlendFactor::Zero);
+
+    pub const fn new(colour_src: BlendFactor, colour_dst: BlendFactor, alpha_src: BlendFactor, alpha_dst: BlendFactor) -> Self {
+        Self {
+            colour_src,
+            colour_dst,
+            colour_operation: BlendOperation::Add,
+            alpha_src,
+            alpha_dst,
+            alpha_operation: BlendOperation::Add,
+            constant: Vec4::ZERO
+        }
+    }
+
+    //src is the fragment output and dst the colour already in the attachment, both RGBA
+    pub fn blend(&self, src: Vec4, dst: Vec4) -> Vec4 {
+        let colour = self.colour_operation.combine(
+            src, self.colour_src.factor(src, dst, self.constant),
+            dst, self.colour_dst.factor(src, dst, self.constant)
+        );
+
+        let alpha = self.alpha_operation.combine(
+            src, self.alpha_src.factor(src, dst, self.constant),
+            dst, self.alpha_dst.factor(src, dst, self.constant)
+        );
+
+        colour.truncate().extend(alpha.w)
+    }
+}
+
+//How triangles are drawn, after culling and clipping, lines and points are not affected
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum FillMode {
+    #[default]
+    Solid,
+    //Edges as lines of line_width
+    Wireframe,
+    //Corners as points of point_size
+    Points,
+    //Solid, with edges of line_width blended over it in the same pass
+    SolidWireframe { colour: Vec4 }
+}
+
+//Offset added to the depth of triangles so coplanar geometry such as decals wins the depth test
+//constant is in units of the depth resolution at the triangle, slope_scale multiplies its steepest screen space depth slope
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DepthBias {
+    pub constant: f32,
+    pub slope_scale: f32,
+    //One sided like D3D, a positive clamp is the largest positive offset and a negative clamp the most negative one
+    //Offsets of the other sign are left as they are, 0 leaves both unbounded
+    pub clamp: f32
+}
+
+impl DepthBias {
+
+    pub fn is_none(&self) -> bool {
+        self.constant == 0.0 && self.slope_scale == 0.0
+    }
+
+    //max_depth is the largest depth of the triangle and max_slope its largest depth change per pixel
+    pub fn offset(&self, max_depth: f32, max_slope: f32) -> f32 {
+        let resolution = f32::EPSILON * max_depth.abs().max(f32::MIN_POSITIVE);
+        let offset = self.constant * resolution + self.slope_scale * max_slope;
+
+        if self.clamp > 0.0 { offset.min(self.clamp) }
+        else if self.clamp < 0.0 { offset.max(self.clamp) }
+        else { offset }
+    }
+}
+
+//Fixed function state used by a draw
+#[derive(Debug, Clone, Copy)]
+pub struct RenderState {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub depth_function: CompareFunction,
+    pub depth_write: bool,
+    pub colour_mask: ColourMask,
+    //None overwrites the attachment
+    pub blend: Option<BlendState>,
+    //None skips the stencil test and leaves the attachment untouched
+    pub stencil: Option<StencilState>,
+    //In pixels, lines are drawn as quads of this width and points as squares of this size
+    pub line_width: f32,
+    pub point_size: f32,
+    pub fill_mode: FillMode,
+    pub depth_bias: DepthBias,
+    //Keeps geometry past the far plane instead of clipping it, its depth ends up clamped to [0, 1] like every fragment
+    pub depth_clamp: bool
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self {
+            cull_mode: CullMode::default(),
+            front_face: FrontFace::default(),
+            depth_function: CompareFunction::default(),
+            depth_write: true,
+            colour_mask: ColourMask::default(),
+            blend: None,
+            stencil: None,
+            line_width: 1.0,
+            point_size: 1.0,
+            fill_mode: FillMode::default(),
+            depth_bias: DepthBias::default(),
+            depth_clamp: false
+        }
+    }
+}
+
+impl RenderState {
+    pub fn is_culled(&self, front_facing: bool) -> bool {
+        match self.cull_mode {
+            CullMode::None => false,
+            CullMode::Front => front_facing,
+            CullMode::Back => !front_facing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+    use super::{BlendOperation, BlendState, ColourMask, CompareFunction, DepthBias, StencilFaceState, StencilOperation, StencilState};
+
+    #[test]
+    fn colour_mask_keeps_disabled_channels() {
+        let mask = ColourMask { red: true, green: false, blue: false, alpha: true };
+        assert_eq!(mask.apply(0xAABBCCDD, 0x11223344), 0xAABB3344);
+        assert_eq!(ColourMask::NONE.apply(0xAABBCCDD, 0x11223344), 0x11223344);
+    }
+
+    #[test]
+    fn alpha_blending() {
+        let src = Vec4::new(1.0, 0.0, 0.0, 0.25);
+        let dst = Vec4::new(0.0, 0.0, 1.0, 1.0);
+
+        let straight = BlendState::ALPHA.blend(src, dst);
+        assert_eq!(straight, Vec4::new(0.25, 0.0, 0.75, 1.0));
+
+        let premultiplied = BlendState::PREMULTIPLIED_ALPHA.blend(Vec4::new(0.25, 0.0, 0.0, 0.25), dst);
+        assert_eq!(premultiplied, straight);
+
+        let max = BlendState { colour_operation: BlendOperation::Max, ..BlendState::ADDITIVE };
+        assert_eq!(max.blend(src, dst).truncate(), glam::Vec3::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn stencil_masks() {
+        let stencil = StencilState { reference: 0x0F, read_mask: 0x0F, write_mask: 0xF0, ..Default::default() };
+
+        assert!(stencil.test(&StencilFaceState::default(), 0xAB));
+        assert!(stencil.test(&StencilFaceState { compare: CompareFunction::Equal, ..Default::default() }, 0x3F));
+        assert_eq!(stencil.update(StencilOperation::Invert, 0x3C), 0xCC);
+        assert_eq!(StencilOperation::DecrementClamp.apply(0, 1), 0);
+        assert_eq!(StencilOperation::DecrementWrap.apply(0, 1), 255);
+    }
+
+    #[test]
+    fn compare_functions() {
+        assert!(CompareFunction::LessEqual.compare(0.5, 0.5));
+        assert!(!CompareFunction::Less.compare(0.5, 0.5));
+        assert!(CompareFunction::Greater.compare(0.6, 0.5));
+        assert!(!CompareFunction::Never.compare(0.0, 1.0));
+    }
+
+    #[test]
+    fn depth_bias_scales_and_clamps() {
+        let bias = DepthBias { constant: 2.0, slope_scale: 0.5, clamp: 0.0 };
+        assert_eq!(bias.offset(1.0, 0.0), 2.0 * f32::EPSILON);
+        assert_eq!(bias.offset(0.5, 0.25), f32::EPSILON + 0.125);
+
+        let clamped = DepthBias { clamp: 0.01, ..bias };
+        assert_eq!(clamped.offset(1.0, 1.0), 0.01);
+
+        let negative = DepthBias { constant: 0.0, slope_scale: -1.0, clamp: -0.01 };
+        assert_eq!(negative.offset(1.0, 1.0), -0.01);
+
+        //Only one side is limited
+        assert_eq!(DepthBias { clamp: 0.01, ..negative }.offset(1.0, 1.0), -1.0);
+    }
+}
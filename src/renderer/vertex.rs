@@ -1,81 +1,385 @@
+use crate::camera::Camera;
 use crate::math;
-use super::data::VertexInput;
+use crate::math::plane::Plane;
+use crate::math::varying::Varying;
 use super::data::VertexOutput;
+use super::primitive::PrimitiveTopology;
+use super::primitive::PrimitiveType;
+use super::state::RenderState;
 
-#[derive(Default)]
-pub struct VertexShader {
-    pub view: glam::Mat4,
-    pub projection: glam::Mat4,
-    pub model: glam::Mat4
+//User-defined vertex stage, invoked once per vertex of every primitive
+pub trait VertexProgram {
+    type Uniforms;
+    type Input;
+    type Varyings: Varying;
+
+    //Returns the clip space position and the varyings that get interpolated across the primitive
+    fn shade(&self, uniforms: &Self::Uniforms, input: &Self::Input, vertex_id: usize) -> (glam::Vec4, Self::Varyings);
+
+    //Signed distances to per-vertex user clip planes, primitives are clipped where they turn negative
+    //Only the first ClipState::clip_distances are read
+    fn clip_distances(&self, _uniforms: &Self::Uniforms, _input: &Self::Input, _vertex_id: usize) -> ClipDistances {
+        ClipDistances::default()
+    }
+}
+
+//Most user clip planes and clip distances a draw can use together
+pub const MAX_USER_CLIP_PLANES: usize = 8;
+
+pub type ClipDistances = [f32; MAX_USER_CLIP_PLANES];
+
+//How primitives crossing the clip volume are handled
+#[derive(Debug, Clone)]
+pub struct ClipState {
+    //Side planes sit this many times outside the clip volume, see math::GUARD_BAND
+    //Kept within 1.0..=math::MAX_GUARD_BAND, so f32::INFINITY is the widest band the rasterizer can take
+    pub guard_band: f32,
+    //Clip space user planes, see Plane::to_clip_space
+    pub user_planes: Vec<glam::Vec4>,
+    //How many of VertexProgram::clip_distances are used, together with user_planes at most MAX_USER_CLIP_PLANES
+    pub clip_distances: usize,
+    //Depth planes of the camera, see set_camera
+    reversed_z: bool,
+    infinite_far: bool
+}
+
+impl Default for ClipState {
+    fn default() -> Self {
+        Self { guard_band: math::GUARD_BAND, user_planes: Vec::new(), clip_distances: 0, reversed_z: false, infinite_far: false }
+    }
+}
+
+impl ClipState {
+
+    //Matches the near and far planes to the projection of the camera the draw is rendered with
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.reversed_z = camera.reversed_z;
+        self.infinite_far = camera.has_infinite_far();
+    }
+
+    //Adds a world space plane, view_projection has to be the one the draw is transformed with
+    pub fn add_world_plane(&mut self, plane: &Plane, view_projection: &glam::Mat4) {
+        self.user_planes.push(plane.to_clip_space(view_projection));
+    }
+
+    //Planes of the clip volume that primitives are culled and clipped against, far is the last one
+    //and is left out with depth clamp, RenderState::depth_clamp, or when it is at infinity
+    fn volume_planes(&self, guard_band: f32, depth_clamp: bool) -> Vec<glam::Vec4> {
+        let mut planes = math::guard_band_planes(guard_band);
+        let far = planes.len() - 1;
+
+        //The volume is the same, near still has to go first so later planes only see w > 0
+        if self.reversed_z {
+            planes.swap(0, far);
+        }
+
+        let count = if depth_clamp || self.infinite_far { far } else { planes.len() };
+        planes[..count].to_vec()
+    }
+
+    //Clip volume and user planes, the per-vertex distances come after them
+    fn planes(&self, depth_clamp: bool) -> Vec<glam::Vec4> {
+        let guard_band = self.guard_band.clamp(1.0, math::MAX_GUARD_BAND);
+        let mut planes = self.volume_planes(guard_band, depth_clamp);
+        planes.extend(self.user_planes.iter().take(MAX_USER_CLIP_PLANES));
+        planes
+    }
+
+    fn distance_count(&self) -> usize {
+        self.clip_distances.min(MAX_USER_CLIP_PLANES.saturating_sub(self.user_planes.len()))
+    }
 }
 
-impl VertexShader {
+//Vertex as seen by the clipper
+type ClipVertex<V> = (glam::Vec4, V, ClipDistances);
+
+fn split_clip_vertex<V: Varying>(start: &ClipVertex<V>, end: &ClipVertex<V>, t: f32) -> ClipVertex<V> {
+    let (position, varyings) = math::split_homogenous(&(start.0, start.1), &(end.0, end.1), t);
 
-    fn triangle_indices(input_indices: &[usize], triangle_id: usize) -> [usize; 3] {
-        [
-            input_indices[triangle_id * 3], input_indices[triangle_id * 3 + 1], input_indices[triangle_id * 3 + 2]
-        ]
+    //Distances are linear in clip space, like the position
+    let mut distances = ClipDistances::default();
+    for (distance, (a, b)) in distances.iter_mut().zip(start.2.iter().zip(&end.2)) {
+        *distance = math::lerp(*a, *b, t);
     }
 
-    fn world_to_clip_space(mvp: &glam::Mat4, positions: &[glam::Vec3; 3]) -> [glam::Vec4; 3] {
-        [
-            mvp.mul_vec4(positions[0].extend(1.0)),
-            mvp.mul_vec4(positions[1].extend(1.0)),
-            mvp.mul_vec4(positions[2].extend(1.0))
-        ]
+    (position, varyings, distances)
+}
+
+pub struct VertexShader<P: VertexProgram> {
+    pub program: P,
+    pub uniforms: P::Uniforms,
+    pub clip: ClipState
+}
+
+impl<P> Default for VertexShader<P>
+where P: VertexProgram + Default, P::Uniforms: Default
+{
+    fn default() -> Self {
+        Self { program: P::default(), uniforms: P::Uniforms::default(), clip: ClipState::default() }
     }
+}
 
-    pub fn dispatch(&self, vertex_in: &VertexInput, indices: &[usize]) -> (VertexOutput, Vec<usize>) {
+impl<P: VertexProgram> VertexShader<P> {
 
-        let input_triangle_count = indices.len() / 3;
+    pub fn new(program: P, uniforms: P::Uniforms) -> Self {
+        Self { program, uniforms, clip: ClipState::default() }
+    }
+
+    //Shades every referenced vertex once, primitives inside the clip volume keep pointing at the shared
+    //output vertices while clipped ones get their own, state has to be the one the output is rasterized with
+    pub fn dispatch(&self, state: &RenderState, topology: PrimitiveTopology, vertex_in: &P::Input, indices: &[usize]) -> (VertexOutput<P::Varyings>, Vec<usize>) {
+
+        let primitives = topology.assemble(indices);
+        let primitive = topology.primitive_type();
+        let planes = self.clip.planes(state.depth_clamp);
+        let cull_planes = self.clip.volume_planes(1.0, state.depth_clamp);
+        let distance_count = self.clip.distance_count();
+
+        //Distance of a vertex to the k-th plane, the per-vertex distances follow the planes
+        let distance = |vertex: &ClipVertex<P::Varyings>, k: usize| {
+            if k < planes.len() { planes[k].dot(vertex.0) } else { vertex.2[k - planes.len()] }
+        };
+        let plane_count = planes.len() + distance_count;
 
         //Outputs
         let mut out_indices = Vec::new();
-        let mut out_vertex = VertexOutput::default();
+        let mut out_vertex = VertexOutput { primitive, ..Default::default() };
 
-        //VP and Frustrum
-        let mvp = self.projection * self.view * self.model;
+        //Post-transform cache, input index -> shaded vertex, and the output vertex it was emitted as
+        let cache_size = primitives.iter().max().map_or(0, |max| max + 1);
+        let mut transformed: Vec<Option<ClipVertex<P::Varyings>>> = vec![None; cache_size];
+        let mut emitted: Vec<Option<usize>> = vec![None; cache_size];
 
         //Main body
-        let mut triangle_start = 0;
-        for i in 0..input_triangle_count {
+        for primitive_indices in primitives.chunks_exact(primitive.vertex_count()) {
 
-            let triangle_indices = VertexShader::triangle_indices(indices, i);
-            let vertices = VertexInput::retrieve(&vertex_in.positions, triangle_indices);
-            let clip_coordinates = VertexShader::world_to_clip_space(&mvp, &vertices);
+            let shaded: Vec<ClipVertex<P::Varyings>> = primitive_indices.iter().map(|index| {
+                *transformed[*index].get_or_insert_with(|| {
+                    let (position, varyings) = self.program.shade(&self.uniforms, vertex_in, *index);
+                    let distances = if distance_count > 0 { self.program.clip_distances(&self.uniforms, vertex_in, *index) } else { ClipDistances::default() };
+                    (position, varyings, distances)
+                })
+            }).collect();
 
-            //Frustum clipping
-            if math::should_cull_triangle(clip_coordinates[0], clip_coordinates[1], clip_coordinates[2]) { continue; }         
+            //Frustum culling, points are not clipped so they also have to pass the user planes
+            let positions: Vec<glam::Vec4> = shaded.iter().map(|vertex| vertex.0).collect();
+            let culled = math::outside_any_plane(&positions, &cull_planes)
+                || (primitive == PrimitiveType::Point && (0..plane_count).any(|k| distance(&shaded[0], k) < 0.0));
+            if culled { continue; }
 
-            let clipped_vertices = math::clip_homogenous_triangle(&clip_coordinates);
-            if clipped_vertices.is_empty() { continue; }
+            //Nothing to clip, reuse the shared vertices
+            //Anything within the guard band is left for the rasterizer to bound to the screen
+            if shaded.iter().all(|vertex| (0..plane_count).all(|k| distance(vertex, k) >= 0.0)) {
+                for (index, (position, varyings, _)) in primitive_indices.iter().zip(&shaded) {
+                    let out_index = *emitted[*index].get_or_insert_with(|| Self::emit(&mut out_vertex, *position, *varyings));
+                    out_indices.push(out_index);
+                }
+                continue;
+            }
 
-            for (vert, bary) in &clipped_vertices {
-                
-                let inv_depth = 1.0 / vert.w;
-                out_vertex.ndc_positions.push((*vert * inv_depth).truncate().extend(inv_depth));
+            let clipped_vertices = match primitive {
+                PrimitiveType::Triangle => math::clip_polygon_by(&shaded, plane_count, distance, split_clip_vertex),
+                PrimitiveType::Line => math::clip_line_by(shaded[0], shaded[1], plane_count, distance, split_clip_vertex).map_or(Vec::new(), |line| line.to_vec()),
+                PrimitiveType::Point => Vec::new()
+            };
+            if clipped_vertices.is_empty() { continue; }
 
-                let colours = VertexInput::retrieve(&vertex_in.colours, triangle_indices);
-                let uvs = VertexInput::retrieve(&vertex_in.uvs, triangle_indices);
+            let polygon_start = out_vertex.ndc_positions.len();
+            for (vert, varyings, _) in &clipped_vertices {
+                Self::emit(&mut out_vertex, *vert, varyings.provoke(&shaded[0].1));
+            }
 
-                let result_colour = math::barycentric_lerp(*bary, colours[0], colours[1],colours[2]);
-                let result_uv = math::barycentric_lerp(*bary, uvs[0], uvs[1],uvs[2]);
-                out_vertex.colours.push(result_colour * inv_depth);
-                out_vertex.uvs.push(result_uv * inv_depth);
+            if primitive == PrimitiveType::Line {
+                out_indices.extend([polygon_start, polygon_start + 1]);
+                continue;
             }
 
             let triangulation_indices: Vec<(usize, usize, usize)> = (1..clipped_vertices.len() - 1)
                 .map(|v| { (0, v, v + 1) }).collect();
 
             for elem in &triangulation_indices {
-                out_indices.push(triangle_start + elem.0);
-                out_indices.push(triangle_start + elem.1);
-                out_indices.push(triangle_start + elem.2);
+                out_indices.push(polygon_start + elem.0);
+                out_indices.push(polygon_start + elem.1);
+                out_indices.push(polygon_start + elem.2);
             }
-
-            triangle_start += clipped_vertices.len();
         }
 
         (out_vertex, out_indices)
     }
-}
\ No newline at end of file
+
+    //Appends a vertex after the perspective divide, returns its index
+    fn emit(out_vertex: &mut VertexOutput<P::Varyings>, position: glam::Vec4, varyings: P::Varyings) -> usize {
+        let inv_depth = 1.0 / position.w;
+        out_vertex.ndc_positions.push((position * inv_depth).truncate().extend(inv_depth));
+        out_vertex.depths.push(position.z as f64 / position.w as f64);
+        out_vertex.varyings.push(varyings);
+        out_vertex.ndc_positions.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use glam::{Vec3, Vec4};
+    use crate::math;
+    use crate::math::plane::Plane;
+    use crate::camera::{Camera, Projection};
+    use crate::texture::FrameBuffer;
+    use crate::renderer::fragment::FragmentShader;
+    use crate::renderer::primitive::PrimitiveTopology;
+    use crate::renderer::programs::{ColourFragmentProgram, ColourVertexInput, ColourVertexProgram};
+    use crate::renderer::state::{CompareFunction, RenderState};
+    use super::{ClipDistances, VertexProgram, VertexShader};
+
+    //Positions are already in clip space, counts its invocations
+    #[derive(Default)]
+    struct Passthrough {
+        invocations: Cell<usize>
+    }
+
+    impl VertexProgram for Passthrough {
+        type Uniforms = ();
+        type Input = Vec<Vec3>;
+        type Varyings = f32;
+
+        fn shade(&self, _uniforms: &(), input: &Vec<Vec3>, vertex_id: usize) -> (Vec4, f32) {
+            self.invocations.set(self.invocations.get() + 1);
+            (input[vertex_id].extend(1.0), vertex_id as f32)
+        }
+
+        //Keeps everything above y = 0
+        fn clip_distances(&self, _uniforms: &(), input: &Vec<Vec3>, vertex_id: usize) -> ClipDistances {
+            let mut distances = ClipDistances::default();
+            distances[0] = input[vertex_id].y;
+            distances
+        }
+    }
+
+    #[test]
+    fn shared_vertices_are_shaded_once() {
+        let vs = VertexShader::<Passthrough>::default();
+        let quad = vec![
+            Vec3::new(-0.5, 0.5, 0.5), Vec3::new(-0.5, -0.5, 0.5),
+            Vec3::new(0.5, -0.5, 0.5), Vec3::new(0.5, 0.5, 0.5)
+        ];
+
+        let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &quad, &[0, 1, 2, 2, 3, 0]);
+
+        assert_eq!(vs.program.invocations.get(), 4);
+        assert_eq!(output.ndc_positions.len(), 4);
+        assert_eq!(indices, vec![0, 1, 2, 2, 3, 0]);
+    }
+
+    #[test]
+    fn clipped_triangles_get_new_vertices() {
+        let mut vs = VertexShader::<Passthrough>::default();
+        vs.clip.guard_band = 1.0;
+        let vertices = vec![
+            Vec3::new(-0.5, 0.5, 0.5), Vec3::new(-0.5, -0.5, 0.5),
+            Vec3::new(0.5, -0.5, 0.5), Vec3::new(2.0, 0.5, 0.5)
+        ];
+
+        //The second triangle crosses the right plane
+        let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &vertices, &[0, 1, 2, 2, 3, 0]);
+
+        assert_eq!(vs.program.invocations.get(), 4);
+        assert_eq!(&indices[..3], &[0, 1, 2]);
+        assert!(output.ndc_positions.len() > 4);
+        assert!(indices[3..].iter().all(|index| *index >= 3));
+    }
+
+    #[test]
+    fn guard_band_only_splits_at_depth_planes() {
+        let vs = VertexShader::<Passthrough>::default();
+        let vertices = vec![
+            Vec3::new(-0.5, 0.5, 0.5), Vec3::new(-0.5, -0.5, 0.5),
+            Vec3::new(4.0, -0.5, 0.5), Vec3::new(0.5, 0.5, -0.5)
+        ];
+
+        //Reaching past the right plane is left to the rasterizer
+        let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &vertices, &[0, 1, 2]);
+        assert_eq!(output.ndc_positions.len(), 3);
+        assert_eq!(indices, vec![0, 1, 2]);
+
+        //Crossing the near plane is still clipped
+        let (output, _) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &vertices, &[0, 1, 3]);
+        assert!(output.ndc_positions.iter().all(|position| position.z >= 0.0));
+    }
+
+    #[test]
+    fn guard_band_is_clamped() {
+        let mut vs = VertexShader::<ColourVertexProgram>::default();
+        vs.clip.guard_band = f32::INFINITY;
+
+        //Far enough out that its snapped coordinates would overflow the edge equations
+        let huge = ColourVertexInput {
+            positions: vec![Vec3::new(-1.0, 1.0, 0.5), Vec3::new(-1.0, -1.0, 0.5), Vec3::new(1.0e6, -1.0, 0.5)],
+            colours: vec![Vec4::ONE; 3]
+        };
+
+        let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &huge, &[0, 1, 2]);
+        assert!(output.ndc_positions.iter().all(|position| position.x <= math::MAX_GUARD_BAND + 1e-3));
+
+        let mut target = FrameBuffer::new(640, 480);
+        target.clear(0, 1.0, 0);
+        FragmentShader::<ColourFragmentProgram>::default().dispatch(&RenderState::default(), &mut target, &output, &indices);
+        assert_ne!(target.colour.read(639, 470), 0);
+    }
+
+    #[test]
+    fn user_planes_and_clip_distances() {
+        let triangle = vec![Vec3::new(-0.5, 0.5, 0.5), Vec3::new(-0.5, -0.5, 0.5), Vec3::new(0.5, -0.5, 0.5)];
+
+        //Positions are already in clip space, so the plane is given with an identity transform
+        let mut vs = VertexShader::<Passthrough>::default();
+        vs.clip.add_world_plane(&Plane::new(Vec3::X, 0.0), &glam::Mat4::IDENTITY);
+
+        let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &triangle, &[0, 1, 2]);
+        assert!(!indices.is_empty());
+        assert!(indices.iter().all(|index| output.ndc_positions[*index].x >= -1e-6));
+
+        let mut vs = VertexShader::<Passthrough>::default();
+        vs.clip.clip_distances = 1;
+
+        let (output, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::TriangleList, &triangle, &[0, 1, 2]);
+        assert!(!indices.is_empty());
+        assert!(indices.iter().all(|index| output.ndc_positions[*index].y >= -1e-6));
+
+        //Entirely on the clipped side
+        let (_, indices) = vs.dispatch(&RenderState::default(), PrimitiveTopology::PointList, &triangle, &[1, 2]);
+        assert!(indices.is_empty());
+    }
+
+    #[test]
+    fn depth_planes_follow_the_camera() {
+        let mut camera = Camera {
+            projection: Projection::Perspective { fov: 1.0 }, aspect_ratio: 1.0, near: 0.1, far: 10.0, infinite_far: true, ..Default::default()
+        };
+
+        let mut vs = VertexShader::<ColourVertexProgram>::default();
+        (vs.uniforms.view, vs.uniforms.projection) = camera.generate_view_projection();
+        vs.clip.set_camera(&camera);
+
+        //Covers the screen far past where a far plane would be, its depth rounds to the edge of the range
+        let distant = ColourVertexInput {
+            positions: vec![Vec3::new(-3.0e7, 3.0e7, -1.0e7), Vec3::new(-3.0e7, -3.0e7, -1.0e7), Vec3::new(3.0e7, 0.0, -1.0e7)],
+            colours: vec![Vec4::ONE; 3]
+        };
+
+        let state = RenderState { depth_function: CompareFunction::LessEqual, ..Default::default() };
+        let (output, indices) = vs.dispatch(&state, PrimitiveTopology::TriangleList, &distant, &[0, 1, 2]);
+
+        let mut target = FrameBuffer::new(64, 64);
+        target.clear(0, camera.far_depth(), 0);
+        FragmentShader::<ColourFragmentProgram>::default().dispatch(&state, &mut target, &output, &indices);
+        assert!(target.colour.as_slice().iter().all(|colour| *colour != 0));
+
+        //Orthographic projections keep their far plane, reversed-Z clips near first
+        camera.projection = Projection::Orthographic { size: 2.0 };
+        camera.reversed_z = true;
+        vs.clip.set_camera(&camera);
+        let planes = vs.clip.planes(false);
+        assert_eq!(planes.len(), 6);
+        assert_eq!(planes[0], Vec4::new(0.0, 0.0, -1.0, 1.0));
+    }
+}
@@ -2,11 +2,11 @@ use std::path::Path;
 use stb_image::image;
 use crate::math;
 
-#[derive(Default)]
 pub struct Texture {
     data: Vec<u32>,
     width: usize,
-    height: usize
+    height: usize,
+    samples: SampleCount
 }
 
 pub fn load_image_file(path: &Path) -> Result<Texture, String> {
@@ -16,7 +16,7 @@ pub fn load_image_file(path: &Path) -> Result<Texture, String> {
         image::LoadResult::ImageU8(image) => {
             Ok(load_image_memory(&image))
         }
-        image::LoadResult::ImageF32(image) => {
+        image::LoadResult::ImageF32(_) => {
             Err("Float images not supported".to_string())
         }
         image::LoadResult::Error(msg) => {
@@ -59,28 +59,80 @@ fn load_image_memory(image: &image::Image<u8>) -> Texture {
     Texture::from_data(out_data, image.width, image.height)
 }
 
-//ARGB texture
+//Samples per pixel of an attachment, positions follow the standard D3D patterns
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SampleCount {
+    #[default]
+    X1,
+    X2,
+    X4,
+    X8
+}
+
+const SAMPLE_POSITIONS_1: [(f32, f32); 1] = [(0.5, 0.5)];
+const SAMPLE_POSITIONS_2: [(f32, f32); 2] = [(0.75, 0.75), (0.25, 0.25)];
+const SAMPLE_POSITIONS_4: [(f32, f32); 4] = [(0.375, 0.125), (0.875, 0.375), (0.125, 0.625), (0.625, 0.875)];
+const SAMPLE_POSITIONS_8: [(f32, f32); 8] = [
+    (0.5625, 0.3125), (0.4375, 0.6875), (0.8125, 0.5625), (0.3125, 0.1875),
+    (0.1875, 0.8125), (0.0625, 0.4375), (0.6875, 0.9375), (0.9375, 0.0625)
+];
+
+impl SampleCount {
+    pub fn count(&self) -> usize {
+        match self {
+            SampleCount::X1 => 1,
+            SampleCount::X2 => 2,
+            SampleCount::X4 => 4,
+            SampleCount::X8 => 8
+        }
+    }
+
+    //Offsets inside the pixel, (0, 0) being its top left corner
+    pub fn positions(&self) -> &'static [(f32, f32)] {
+        match self {
+            SampleCount::X1 => &SAMPLE_POSITIONS_1,
+            SampleCount::X2 => &SAMPLE_POSITIONS_2,
+            SampleCount::X4 => &SAMPLE_POSITIONS_4,
+            SampleCount::X8 => &SAMPLE_POSITIONS_8
+        }
+    }
+}
+
+//ARGB texture, samples of a pixel are stored next to each other
 impl Texture {
     pub fn new(width: usize, height: usize) -> Self {
-        Self { data: vec![0; width * height], width, height}
+        Self::new_multisampled(width, height, SampleCount::X1)
+    }
+
+    pub fn new_multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
+        Self { data: vec![0; width * height * samples.count()], width, height, samples}
     }
 
     pub fn from_data(data: Vec<u32>, width: usize, height: usize) -> Self {
         debug_assert!(width * height == data.len());
-        Self { data, width, height}
+        Self { data, width, height, samples: SampleCount::X1}
     }
 
     pub fn width(&self) -> usize { self.width }
     pub fn height(&self) -> usize { self.height }
+    pub fn samples(&self) -> SampleCount { self.samples }
 
     //RGB
     pub fn read(&self, i: usize, j: usize) -> u32 {
-        let index = self.width * j + i;
-        self.data[index]
+        self.read_sample(i, j, 0)
     }
 
     pub fn write(&mut self, i: usize, j: usize, colour: u32) {
-        let index = self.width * j + i;
+        self.write_sample(i, j, 0, colour)
+    }
+
+    pub fn read_sample(&self, i: usize, j: usize, sample: usize) -> u32 {
+        let index = (self.width * j + i) * self.samples.count() + sample;
+        self.data[index]
+    }
+
+    pub fn write_sample(&mut self, i: usize, j: usize, sample: usize, colour: u32) {
+        let index = (self.width * j + i) * self.samples.count() + sample;
         self.data[index] = colour;
     }
 
@@ -88,54 +140,343 @@ impl Texture {
         self.data.fill(val)
     }
 
+    //Multiplies rgb by alpha, for use with BlendState::PREMULTIPLIED_ALPHA
+    pub fn premultiply_alpha(&mut self) {
+        for texel in self.data.iter_mut() {
+            let colour = math::colour::hex_to_f32(*texel);
+            *texel = math::colour::vec4_to_hex((colour.truncate() * colour.w).extend(colour.w));
+        }
+    }
+
+    //Averages the samples of every pixel into a single sampled texture of the same size
+    pub fn resolve(&self, out: &mut Texture) {
+        debug_assert!(out.width() == self.width && out.height() == self.height);
+        debug_assert!(out.samples() == SampleCount::X1);
+
+        let count = self.samples.count();
+
+        for (pixel, resolved) in self.data.chunks_exact(count).zip(out.data.iter_mut()) {
+            let sum = pixel.iter().fold(glam::Vec4::ZERO, |sum, sample| sum + math::colour::hex_to_f32(*sample));
+            *resolved = math::colour::vec4_to_hex(sum / count as f32);
+        }
+    }
+
     pub fn as_slice(&self) -> &[u32] { &self.data }
 }
 
+impl Default for Texture {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+//Storage of the depth attachment, F64 keeps precision in large scenes with a standard depth range
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DepthFormat {
+    #[default]
+    F32,
+    F64
+}
+
+enum DepthData {
+    F32(Vec<f32>),
+    F64(Vec<f64>)
+}
+
+//Values are read and written as f64 whatever the format
 pub struct DepthTexture {
-    data: Vec<f32>,
+    data: DepthData,
     width: usize,
-    height: usize
+    height: usize,
+    samples: SampleCount
 }
 
 impl DepthTexture {
     pub fn new(width: usize, height: usize) -> Self {
-        Self { data: vec![1.0; width * height], width, height}
+        Self::new_multisampled(width, height, SampleCount::X1)
+    }
+
+    pub fn new_multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
+        Self::new_with_format(width, height, samples, DepthFormat::F32)
+    }
+
+    pub fn new_with_format(width: usize, height: usize, samples: SampleCount, format: DepthFormat) -> Self {
+        let size = width * height * samples.count();
+        let data = match format {
+            DepthFormat::F32 => DepthData::F32(vec![1.0; size]),
+            DepthFormat::F64 => DepthData::F64(vec![1.0; size])
+        };
+
+        Self { data, width, height, samples }
     }
 
     pub fn width(&self) -> usize { self.width }
     pub fn height(&self) -> usize { self.height }
+    pub fn samples(&self) -> SampleCount { self.samples }
 
-    //RGB
-    pub fn read(&self, i: usize, j: usize) -> f32 {
-        let index = self.width * j + i;
+    pub fn format(&self) -> DepthFormat {
+        match self.data {
+            DepthData::F32(_) => DepthFormat::F32,
+            DepthData::F64(_) => DepthFormat::F64
+        }
+    }
+
+    pub fn read(&self, i: usize, j: usize) -> f64 {
+        self.read_sample(i, j, 0)
+    }
+
+    pub fn write(&mut self, i: usize, j: usize, val: f64) {
+        self.write_sample(i, j, 0, val)
+    }
+
+    pub fn read_sample(&self, i: usize, j: usize, sample: usize) -> f64 {
+        let index = (self.width * j + i) * self.samples.count() + sample;
+        match &self.data {
+            DepthData::F32(data) => data[index] as f64,
+            DepthData::F64(data) => data[index]
+        }
+    }
+
+    pub fn write_sample(&mut self, i: usize, j: usize, sample: usize, val: f64) {
+        let index = (self.width * j + i) * self.samples.count() + sample;
+        match &mut self.data {
+            DepthData::F32(data) => data[index] = val as f32,
+            DepthData::F64(data) => data[index] = val
+        }
+    }
+
+    pub fn clear(&mut self, val: f64) {
+        match &mut self.data {
+            DepthData::F32(data) => data.fill(val as f32),
+            DepthData::F64(data) => data.fill(val)
+        }
+    }
+
+    //Contiguous pieces of `length` values, the last one may be shorter
+    fn chunks_mut(&mut self, length: usize) -> Vec<DepthSlice<'_>> {
+        match &mut self.data {
+            DepthData::F32(data) => data.chunks_mut(length).map(DepthSlice::F32).collect(),
+            DepthData::F64(data) => data.chunks_mut(length).map(DepthSlice::F64).collect()
+        }
+    }
+}
+
+enum DepthSlice<'a> {
+    F32(&'a mut [f32]),
+    F64(&'a mut [f64])
+}
+
+//8 bit stencil attachment
+pub struct StencilTexture {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    samples: SampleCount
+}
+
+impl StencilTexture {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::new_multisampled(width, height, SampleCount::X1)
+    }
+
+    pub fn new_multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
+        Self { data: vec![0; width * height * samples.count()], width, height, samples}
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+    pub fn samples(&self) -> SampleCount { self.samples }
+
+    pub fn read(&self, i: usize, j: usize) -> u8 {
+        self.read_sample(i, j, 0)
+    }
+
+    pub fn write(&mut self, i: usize, j: usize, val: u8) {
+        self.write_sample(i, j, 0, val)
+    }
+
+    pub fn read_sample(&self, i: usize, j: usize, sample: usize) -> u8 {
+        let index = (self.width * j + i) * self.samples.count() + sample;
         self.data[index]
     }
 
-    pub fn write(&mut self, i: usize, j: usize, val: f32) {
-        let index = self.width * j + i;
+    pub fn write_sample(&mut self, i: usize, j: usize, sample: usize, val: u8) {
+        let index = (self.width * j + i) * self.samples.count() + sample;
         self.data[index] = val;
     }
 
-    pub fn clear(&mut self, val: f32) {
+    pub fn clear(&mut self, val: u8) {
         self.data.fill(val)
     }
+}
+
+//The attachments a draw renders into
+pub struct FrameBuffer {
+    pub colour: Texture,
+    pub depth: DepthTexture,
+    pub stencil: StencilTexture
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::new_multisampled(width, height, SampleCount::X1)
+    }
+
+    pub fn new_multisampled(width: usize, height: usize, samples: SampleCount) -> Self {
+        Self::new_with_depth_format(width, height, samples, DepthFormat::F32)
+    }
+
+    pub fn new_with_depth_format(width: usize, height: usize, samples: SampleCount, depth_format: DepthFormat) -> Self {
+        Self {
+            colour: Texture::new_multisampled(width, height, samples),
+            depth: DepthTexture::new_with_format(width, height, samples, depth_format),
+            stencil: StencilTexture::new_multisampled(width, height, samples)
+        }
+    }
+
+    pub fn width(&self) -> usize { self.colour.width() }
+    pub fn height(&self) -> usize { self.colour.height() }
+    pub fn samples(&self) -> SampleCount { self.colour.samples() }
+
+    pub fn clear(&mut self, colour: u32, depth: f64, stencil: u8) {
+        self.colour.clear(colour);
+        self.depth.clear(depth);
+        self.stencil.clear(stencil);
+    }
+
+    //Resolves the colour attachment into a single sampled texture, e.g. for presenting
+    pub fn resolve(&self, out: &mut Texture) {
+        self.colour.resolve(out);
+    }
+
+    //The whole frame buffer as a single band
+    pub fn as_band(&mut self) -> FrameBufferBand<'_> {
+        let samples = self.samples();
+        let rows = self.height();
+        self.bands_mut(rows).pop().unwrap_or_else(|| FrameBufferBand::empty(samples))
+    }
 
-    //replaces and returns true if input < previous value
-    pub fn depth_test(&mut self, i: usize, j: usize, depth_val: f32) -> bool {
-        if depth_val < self.read(i, j) { self.write(i, j, depth_val); true}
-        else { false }
+    //Splits the attachments into bands of `rows` rows (the last one may be shorter) that can be written in parallel
+    pub fn bands_mut(&mut self, rows: usize) -> Vec<FrameBufferBand<'_>> {
+        let width = self.width();
+        let samples = self.samples();
+        let row_length = width * samples.count();
+        if row_length * rows == 0 { return Vec::new(); }
+
+        let colour = self.colour.data.chunks_mut(row_length * rows);
+        let depth = self.depth.chunks_mut(row_length * rows);
+        let stencil = self.stencil.data.chunks_mut(row_length * rows);
+
+        colour.zip(depth).zip(stencil)
+            .enumerate()
+            .map(|(band, ((colour, depth), stencil))| FrameBufferBand {
+                start_row: band * rows,
+                rows: colour.len() / row_length,
+                colour, depth, stencil, width, samples
+            })
+            .collect()
     }
 }
 
+//Mutable view over the rows [start_row, start_row + rows) of a frame buffer, addressed with frame buffer coordinates
+pub struct FrameBufferBand<'a> {
+    colour: &'a mut [u32],
+    depth: DepthSlice<'a>,
+    stencil: &'a mut [u8],
+    width: usize,
+    start_row: usize,
+    rows: usize,
+    samples: SampleCount
+}
+
+impl<'a> FrameBufferBand<'a> {
+    fn empty(samples: SampleCount) -> Self {
+        Self { colour: &mut [], depth: DepthSlice::F32(&mut []), stencil: &mut [], width: 0, start_row: 0, rows: 0, samples }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn start_row(&self) -> usize { self.start_row }
+    pub fn rows(&self) -> std::ops::Range<usize> { self.start_row..self.start_row + self.rows }
+    pub fn samples(&self) -> SampleCount { self.samples }
+
+    fn index(&self, i: usize, j: usize, sample: usize) -> usize {
+        debug_assert!(self.rows().contains(&j));
+        (self.width * (j - self.start_row) + i) * self.samples.count() + sample
+    }
+
+    pub fn read_colour(&self, i: usize, j: usize, sample: usize) -> u32 { self.colour[self.index(i, j, sample)] }
+    pub fn write_colour(&mut self, i: usize, j: usize, sample: usize, val: u32) { self.colour[self.index(i, j, sample)] = val; }
+
+    pub fn read_depth(&self, i: usize, j: usize, sample: usize) -> f64 {
+        let index = self.index(i, j, sample);
+        match &self.depth {
+            DepthSlice::F32(depth) => depth[index] as f64,
+            DepthSlice::F64(depth) => depth[index]
+        }
+    }
+
+    pub fn write_depth(&mut self, i: usize, j: usize, sample: usize, val: f64) {
+        let index = self.index(i, j, sample);
+        match &mut self.depth {
+            DepthSlice::F32(depth) => depth[index] = val as f32,
+            DepthSlice::F64(depth) => depth[index] = val
+        }
+    }
+
+    pub fn read_stencil(&self, i: usize, j: usize, sample: usize) -> u8 { self.stencil[self.index(i, j, sample)] }
+    pub fn write_stencil(&mut self, i: usize, j: usize, sample: usize, val: u8) { self.stencil[self.index(i, j, sample)] = val; }
+}
+
+//What a sampler reads for uvs outside [0, 1]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddressMode {
+    //The closest edge texel
+    #[default]
+    ClampToEdge,
+    //The texture tiles, only the fractional part of the uvs is used
+    Repeat
+}
+
 #[derive(Debug, Default)]
 pub struct Sampler {
-
+    pub address_mode: AddressMode
 }
 
 impl Sampler {
     pub fn sample(&self, texture: &Texture, uv: glam::Vec2) -> glam::Vec4 {
-        let dimensions = ((texture.width() - 1) as f32, (texture.height() - 1) as f32);
-        let (i, j) = (uv.x * dimensions.0, (1.0 - uv.y) * dimensions.1);
-        math::colour::hex_to_f32(texture.read(i.round() as usize, j.round() as usize))
+        let (width, height) = (texture.width(), texture.height());
+
+        let (i, j) = match self.address_mode {
+            AddressMode::ClampToEdge => {
+                let uv = uv.clamp(glam::Vec2::ZERO, glam::Vec2::ONE);
+                let (i, j) = (uv.x * (width - 1) as f32, (1.0 - uv.y) * (height - 1) as f32);
+                (i.round() as usize, j.round() as usize)
+            }
+            AddressMode::Repeat => {
+                let wrap = |coordinate: f32, size: usize| (coordinate.floor() as i64).rem_euclid(size as i64) as usize;
+                (wrap(uv.x * width as f32, width), wrap((1.0 - uv.y) * height as f32, height))
+            }
+        };
+
+        math::colour::hex_to_f32(texture.read(i, j))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use super::{AddressMode, Sampler, Texture};
+
+    #[test]
+    fn address_modes_handle_uvs_outside_the_texture() {
+        //Bottom row red and green, top row blue and white
+        let texture = Texture::from_data(vec![0xFF0000FF, 0xFFFFFFFF, 0xFFFF0000, 0xFF00FF00], 2, 2);
+        let blue = Sampler::default().sample(&texture, Vec2::new(0.0, 1.0));
+
+        let clamp = Sampler::default();
+        assert_eq!(clamp.sample(&texture, Vec2::new(-3.0, 7.5)), blue);
+
+        let repeat = Sampler { address_mode: AddressMode::Repeat };
+        assert_eq!(repeat.sample(&texture, Vec2::new(-1.75, 2.75)), blue);
+        assert_eq!(repeat.sample(&texture, Vec2::new(3.75, 0.25)), clamp.sample(&texture, Vec2::new(1.0, 0.0)));
+    }
+}